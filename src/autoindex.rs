@@ -0,0 +1,232 @@
+//! Listing pages for static directories.
+extern crate serde_json;
+extern crate walkdir;
+
+use std::io;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+use self::walkdir::WalkDir;
+
+use request::Request;
+use response::Response;
+use status::StatusCode;
+use utils::date::{format_http_date, unix_seconds};
+use utils::file::is_hidden;
+use utils::url::percent_encode_path;
+
+/// A file or directory in a listing.
+#[derive(Debug, Clone, Serialize)]
+pub struct Entry {
+    pub name: String,
+    pub is_dir: bool,
+    /// The size in bytes. Directories have a size of 0.
+    pub size: u64,
+    /// The modification time in seconds since the epoch.
+    pub modified: Option<u64>,
+}
+
+/// The column a listing is sorted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Name,
+    Size,
+    Modified,
+}
+
+impl SortKey {
+    /// Parses the `sort` query parameter.
+    pub fn from_query(value: &str) -> Option<SortKey> {
+        match value {
+            "name" => Some(SortKey::Name),
+            "size" => Some(SortKey::Size),
+            "mtime" => Some(SortKey::Modified),
+            _ => None,
+        }
+    }
+
+    fn as_query(&self) -> &'static str {
+        match *self {
+            SortKey::Name => "name",
+            SortKey::Size => "size",
+            SortKey::Modified => "mtime",
+        }
+    }
+}
+
+/// Reads the entries of a directory, skipping hidden files.
+pub fn read_entries(dir: &Path) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for entry in WalkDir::new(dir).min_depth(1).max_depth(1).into_iter()
+        .filter_entry(|e| !is_hidden(e)) {
+        let entry = entry.map_err(io::Error::from)?;
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        entries.push(Entry {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            is_dir: metadata.is_dir(),
+            size: if metadata.is_dir() { 0 } else { metadata.len() },
+            modified: metadata.modified().ok().map(unix_seconds),
+        });
+    }
+    Ok(entries)
+}
+
+/// Sorts the entries by the key. Directories are always listed first.
+pub fn sort_entries(entries: &mut [Entry], key: SortKey, descending: bool) {
+    entries.sort_by(|a, b| {
+        let order = match key {
+            SortKey::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            SortKey::Size => a.size.cmp(&b.size),
+            SortKey::Modified => a.modified.cmp(&b.modified),
+        };
+        let order = order.then_with(|| a.name.cmp(&b.name));
+        let order = if descending { order.reverse() } else { order };
+        b.is_dir.cmp(&a.is_dir).then(order)
+    });
+}
+
+/// Creates the listing response for the directory at `url`. The `sort`
+/// (`name`, `size` or `mtime`) and `order` (`asc` or `desc`) query parameters
+/// choose the order. The listing is JSON if `format=json` is in the query or
+/// the client only accepts JSON.
+pub fn listing(request: &Request, dir: &Path, url: &str, has_parent: bool) -> io::Result<Response> {
+    let key = request.query("sort").and_then(SortKey::from_query).unwrap_or(SortKey::Name);
+    let descending = request.query("order") == Some("desc");
+    let mut entries = read_entries(dir)?;
+    sort_entries(&mut entries, key, descending);
+
+    let json = request.query("format") == Some("json")
+        || request.header("accept").map_or(false, |a| a.trim().starts_with("application/json"));
+    let (body, content_type) = if json {
+        let body = serde_json::to_string(&entries).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        (body, "application/json")
+    } else {
+        (render_html(url, &entries, key, descending, has_parent), "text/html; charset=utf-8")
+    };
+
+    let mut response = Response::new(StatusCode::OK, body);
+    response.add_response_headers(&format!("Content-Type: {}", content_type));
+    response.add_response_headers("Vary: Accept");
+    Ok(response)
+}
+
+/// Renders the entries as an HTML table with links to each entry.
+pub fn render_html(url: &str, entries: &[Entry], key: SortKey, descending: bool, has_parent: bool) -> String {
+    let base = url.trim_end_matches('/');
+    let title = escape_html(&format!("Index of {}/", base));
+
+    let header = |column: SortKey, label: &str| {
+        let order = if column == key && !descending { "desc" } else { "asc" };
+        format!("<th><a href=\"?sort={}&amp;order={}\">{}</a></th>", column.as_query(), order, label)
+    };
+
+    let mut rows = String::new();
+    if has_parent {
+        let parent = &base[..base.rfind('/').unwrap_or(0)];
+        rows.push_str(&format!("<tr><td><a href=\"{}/\">../</a></td><td></td><td>-</td></tr>\n",
+                               escape_html(&percent_encode_path(parent))));
+    }
+    for entry in entries {
+        let suffix = if entry.is_dir { "/" } else { "" };
+        let href = percent_encode_path(&format!("{}/{}", base, entry.name));
+        let modified = entry.modified
+            .map(|m| format_http_date(UNIX_EPOCH + Duration::from_secs(m)))
+            .unwrap_or_default();
+        let size = if entry.is_dir { "-".to_string() } else { entry.size.to_string() };
+        rows.push_str(&format!("<tr><td><a href=\"{}{}\">{}{}</a></td><td>{}</td><td>{}</td></tr>\n",
+                               escape_html(&href), suffix, escape_html(&entry.name), suffix, modified, size));
+    }
+
+    format!(r#"<!doctype html>
+<html lang="en">
+<head>
+    <meta charset="utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    <h1>{title}</h1>
+    <table>
+<tr>{name}{modified}{size}</tr>
+{rows}    </table>
+</body>
+</html>
+"#, title = title, rows = rows,
+        name = header(SortKey::Name, "Name"),
+        modified = header(SortKey::Modified, "Last modified"),
+        size = header(SortKey::Size, "Size"))
+}
+
+/// Escapes the characters with a special meaning in HTML text and attributes.
+fn escape_html(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+    use std::fs::{create_dir, File};
+    use std::io::Write;
+    use self::tempdir::TempDir;
+
+    fn entry(name: &str, is_dir: bool, size: u64, modified: u64) -> Entry {
+        Entry { name: name.to_string(), is_dir, size, modified: Some(modified) }
+    }
+
+    #[test]
+    fn test_read_entries_skips_hidden_files() {
+        let dir = TempDir::new("rhs-tests").unwrap();
+        File::create(dir.path().join("a.txt")).unwrap().write_all(b"hello").unwrap();
+        File::create(dir.path().join(".secret")).unwrap();
+        create_dir(dir.path().join("sub")).unwrap();
+
+        let mut entries = read_entries(dir.path()).unwrap();
+        sort_entries(&mut entries, SortKey::Name, false);
+
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(vec!["sub", "a.txt"], names);
+        assert_eq!(5, entries[1].size);
+        assert!(entries[0].is_dir);
+    }
+
+    #[test]
+    fn test_sort_entries() {
+        let mut entries = vec![entry("b", false, 10, 1), entry("A", false, 30, 3),
+                               entry("c", false, 20, 2), entry("dir", true, 0, 0)];
+
+        sort_entries(&mut entries, SortKey::Size, true);
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(vec!["dir", "A", "c", "b"], names);
+
+        sort_entries(&mut entries, SortKey::Name, false);
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(vec!["dir", "A", "b", "c"], names);
+    }
+
+    #[test]
+    fn test_render_html() {
+        let entries = vec![entry("my <file>.txt", false, 3, 0), entry("sub", true, 0, 0)];
+        let html = render_html("/static/docs", &entries, SortKey::Name, false, true);
+
+        assert!(html.contains("<title>Index of /static/docs/</title>"));
+        assert!(html.contains("<a href=\"/static/\">../</a>"));
+        assert!(html.contains("<a href=\"/static/docs/my%20%3Cfile%3E.txt\">my &lt;file&gt;.txt</a>"));
+        assert!(html.contains("<a href=\"/static/docs/sub/\">sub/</a>"));
+        assert!(html.contains("<a href=\"?sort=name&amp;order=desc\">Name</a>"));
+    }
+}
@@ -0,0 +1,138 @@
+//! Caching headers for static files.
+
+use std::time::{Duration, SystemTime};
+
+use utils::date::format_http_date;
+
+/// Caching headers for the static files whose URL path matches `path`.
+///
+/// `path` is a prefix (`/static/dist/`) unless it contains a wildcard, in
+/// which case it is a glob matched against the whole path: `*` matches
+/// within a segment, `**` matches across segments and `?` matches a single
+/// character (`/**/*.html`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheRule {
+    pub path: String,
+    /// The value of the `Cache-Control` header, e.g. `public, max-age=31536000, immutable`.
+    pub cache_control: Option<String>,
+    /// Seconds from the time of the response used for the `Expires` header.
+    pub expires: Option<u64>,
+    /// The value of the `Vary` header.
+    pub vary: Option<String>,
+}
+
+/// An ordered list of cache rules. The first rule matching a path is used.
+#[derive(Debug, Clone, Default)]
+pub struct CachePolicy {
+    rules: Vec<CacheRule>,
+}
+
+impl CacheRule {
+    /// Creates a rule setting `Cache-Control` for paths matching `path`.
+    pub fn new(path: &str, cache_control: &str) -> CacheRule {
+        CacheRule {
+            path: path.to_string(),
+            cache_control: Some(cache_control.to_string()),
+            expires: None,
+            vary: None,
+        }
+    }
+
+    /// Checks if the rule applies to the URL path.
+    pub fn matches(&self, path: &str) -> bool {
+        if self.path.contains(&['*', '?'][..]) {
+            glob_match(self.path.as_bytes(), path.as_bytes())
+        } else {
+            path.starts_with(&self.path)
+        }
+    }
+
+    /// Returns the header lines set by this rule.
+    pub fn headers(&self) -> Vec<String> {
+        let mut headers = Vec::new();
+        if let Some(ref cache_control) = self.cache_control {
+            headers.push(format!("Cache-Control: {}", cache_control));
+        }
+        if let Some(expires) = self.expires {
+            let expires = SystemTime::now() + Duration::from_secs(expires);
+            headers.push(format!("Expires: {}", format_http_date(expires)));
+        }
+        if let Some(ref vary) = self.vary {
+            headers.push(format!("Vary: {}", vary));
+        }
+        headers
+    }
+}
+
+impl CachePolicy {
+    pub fn new(rules: Vec<CacheRule>) -> CachePolicy {
+        CachePolicy {
+            rules,
+        }
+    }
+
+    /// Returns the header lines for the URL path from the first matching rule.
+    pub fn headers_for(&self, path: &str) -> Vec<String> {
+        self.rules.iter()
+            .find(|rule| rule.matches(path))
+            .map_or_else(Vec::new, |rule| rule.headers())
+    }
+}
+
+/// Matches a glob against a whole path.
+fn glob_match(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern.first() {
+        None => path.is_empty(),
+        Some(&b'*') if pattern.get(1) == Some(&b'*') => {
+            let rest = &pattern[2..];
+            // `/**/` also matches a single `/`.
+            if rest.first() == Some(&b'/') && glob_match(&rest[1..], path) {
+                return true;
+            }
+            (0..path.len() + 1).any(|i| glob_match(rest, &path[i..]))
+        }
+        Some(&b'*') => {
+            let rest = &pattern[1..];
+            let segment_end = path.iter().position(|&b| b == b'/').unwrap_or(path.len());
+            (0..segment_end + 1).any(|i| glob_match(rest, &path[i..]))
+        }
+        Some(&b'?') => !path.is_empty() && path[0] != b'/' && glob_match(&pattern[1..], &path[1..]),
+        Some(&c) => path.first() == Some(&c) && glob_match(&pattern[1..], &path[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(path: &str) -> CacheRule {
+        CacheRule::new(path, "no-cache")
+    }
+
+    #[test]
+    fn test_rule_matching() {
+        assert!(rule("/static/dist/").matches("/static/dist/app.3f2a.js"));
+        assert!(!rule("/static/dist/").matches("/static/app.js"));
+        assert!(rule("/static/*.js").matches("/static/app.js"));
+        assert!(!rule("/static/*.js").matches("/static/dist/app.js"));
+        assert!(rule("/**/*.html").matches("/index.html"));
+        assert!(rule("/**/*.html").matches("/docs/guide/index.html"));
+        assert!(rule("/img/logo.???").matches("/img/logo.png"));
+        assert!(!rule("/img/logo.???").matches("/img/logo.jpeg"));
+    }
+
+    #[test]
+    fn test_first_matching_rule_is_used() {
+        let mut immutable = CacheRule::new("/static/dist/", "public, max-age=31536000, immutable");
+        immutable.expires = Some(31536000);
+        immutable.vary = Some("Accept-Encoding".to_string());
+        let policy = CachePolicy::new(vec![immutable, rule("/**/*.html"), rule("/static/")]);
+
+        let headers = policy.headers_for("/static/dist/app.js");
+        assert_eq!("Cache-Control: public, max-age=31536000, immutable", headers[0]);
+        assert!(headers[1].starts_with("Expires: "));
+        assert_eq!("Vary: Accept-Encoding", headers[2]);
+        assert_eq!(vec!["Cache-Control: no-cache"], policy.headers_for("/static/index.html"));
+        assert!(policy.headers_for("/other.css").is_empty());
+    }
+}
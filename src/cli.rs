@@ -3,8 +3,8 @@ use std::env;
 
 use clap::{App, ArgMatches};
 
-use config::{ConfigBuilder, Config};
-use server::Server;
+use rust_http_server::{ConfigBuilder, Config, Server, set_verbose};
+use rust_http_server::mount::Mount;
 
 pub fn run_cli<'a, 'b>() -> App<'a, 'b> {
     let name = env!("CARGO_PKG_NAME");
@@ -21,6 +21,11 @@ pub fn run_cli<'a, 'b>() -> App<'a, 'b> {
             --cert=[CERT]           'Path to pkcs12 certificate'
             --pwd=[PWD]             'Password for the pkcs12'
             -t, --threads=[THREADS] 'Sets the number of threads to use'
+            --autoindex             'Lists the contents of directories'
+            --spa=[FILE]            'Serves the file for page requests matching no route'
+            --mount=[MOUNT]...      '<url>=<dir> Serves the directory at the URL, finding files when requested'
+            -w, --watch             'Updates the routes when files change'
+            --dev                   'Reloads pages in the browser when files change'
             -l, --log=[LOG_LEVEL]   'Sets the log level (debug, error, info, trace, warn)'
             -v...                   'Sets verbosity'")
 
@@ -55,6 +60,29 @@ pub fn config_from_cli(args: &ArgMatches) -> Config {
         config.set_cert_password(pwd);
     }
 
+    if let Some(file) = args.value_of("spa") {
+        config.set_spa_fallback(file);
+    }
+
+    if let Some(mounts) = args.values_of("mount") {
+        for mount in mounts {
+            let eq = mount.find('=').expect("Please enter mounts as <url>=<dir>");
+            config.add_mount(Mount::new(&mount[..eq], &mount[eq + 1..]));
+        }
+    }
+
+    if args.is_present("dev") {
+        config.set_dev(true);
+    }
+
+    if args.is_present("watch") {
+        config.set_watch(true);
+    }
+
+    if args.is_present("autoindex") {
+        config.set_autoindex(true);
+    }
+
     config.build()
 }
 
@@ -66,8 +94,8 @@ pub fn cli_logging(args: &ArgMatches) {
 
 pub fn cli_verbosity(app: &ArgMatches) {
     match app.occurrences_of("v") {
-        0 => set_verbosity(false),
-        _ => set_verbosity(true),
+        0 => set_verbose(false),
+        _ => set_verbose(true),
     }
 }
 
@@ -76,9 +104,3 @@ pub fn cli_serve_directory(app: &ArgMatches, server: &mut Server) {
         server.serve_directory(dir);
     }
 }
-
-fn set_verbosity(value: bool) {
-    unsafe {
-        ::VERBOSE = value;
-    }
-}
@@ -0,0 +1,483 @@
+//! Content coding negotiation and compression of response bodies.
+extern crate brotli;
+extern crate flate2;
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+
+use self::flate2::Compression as FlateLevel;
+use self::flate2::write::{GzEncoder, ZlibEncoder};
+
+use response::{Body, Response};
+use status::StatusCode;
+
+/// The smallest body compressed when `min_size` isn't configured. Smaller
+/// bodies gain little and can grow.
+pub const DEFAULT_MIN_SIZE: u64 = 1024;
+
+/// The largest body compressed. Compression happens in memory so larger
+/// files are streamed from disk uncompressed.
+pub const MAX_COMPRESS_SIZE: u64 = 8 * 1024 * 1024;
+
+/// A content coding the server can apply to a response body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+    /// The zlib format (RFC 1950) as required for `deflate` by RFC 7230 4.2.2.
+    Deflate,
+}
+
+/// Response compression settings. An algorithm is only used if it has a
+/// level so an empty object disables compression.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Compression {
+    /// The gzip level from 0 to 9.
+    pub gzip: Option<u32>,
+    /// The deflate level from 0 to 9.
+    pub deflate: Option<u32>,
+    /// The brotli quality from 0 to 11.
+    pub brotli: Option<u32>,
+    /// The smallest body in bytes that is compressed. Defaults to `DEFAULT_MIN_SIZE`.
+    pub min_size: Option<u64>,
+}
+
+/// The encodings in order of preference when the client accepts several
+/// equally.
+const PREFERENCE: [Encoding; 3] = [Encoding::Brotli, Encoding::Gzip, Encoding::Deflate];
+
+impl Encoding {
+    /// Returns the name of the coding used in `Content-Encoding`.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    /// Returns the encoding of a precompressed file from its extension,
+    /// e.g. `br` for `app.js.br`.
+    pub fn from_extension(extension: &str) -> Option<Encoding> {
+        match extension {
+            "br" => Some(Encoding::Brotli),
+            "gz" => Some(Encoding::Gzip),
+            _ => None,
+        }
+    }
+
+    fn matches(&self, coding: &str) -> bool {
+        coding.eq_ignore_ascii_case(self.name())
+            || (*self == Encoding::Gzip && coding.eq_ignore_ascii_case("x-gzip"))
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Compression {
+        Compression {
+            gzip: Some(6),
+            deflate: Some(6),
+            brotli: Some(4),
+            min_size: None,
+        }
+    }
+}
+
+impl Compression {
+    /// Returns the configured level of the encoding, `None` if it is disabled.
+    pub fn level(&self, encoding: Encoding) -> Option<u32> {
+        match encoding {
+            Encoding::Brotli => self.brotli,
+            Encoding::Gzip => self.gzip,
+            Encoding::Deflate => self.deflate,
+        }
+    }
+
+    /// Chooses the enabled encoding with the highest q-value in an
+    /// `Accept-Encoding` header. `None` means the body is sent as is.
+    pub fn negotiate(&self, accept_encoding: Option<&str>) -> Option<Encoding> {
+        let enabled: Vec<Encoding> = PREFERENCE.iter().cloned().filter(|&e| self.level(e).is_some()).collect();
+        negotiate(accept_encoding, &enabled)
+    }
+
+    /// Compresses the body of the response if its content type is
+    /// compressible and the client accepts an enabled encoding. Responses that
+    /// could be compressed get `Vary: Accept-Encoding`. The `ETag` of a
+    /// compressed response is made weak since the bytes differ from the file.
+    pub fn compress_response(&self, response: &mut Response, accept_encoding: Option<&str>) -> io::Result<()> {
+        let encoding = match self.response_encoding(response, accept_encoding) {
+            Some(encoding) => encoding,
+            None => return Ok(()),
+        };
+
+        let data = match *response.body() {
+            Body::Text(ref text) => text.as_bytes().to_vec(),
+            Body::Bytes(ref bytes) => bytes.clone(),
+            Body::File(ref file, length) => {
+                let mut data = Vec::with_capacity(length as usize);
+                let file: &File = file;
+                file.take(length).read_to_end(&mut data)?;
+                data
+            }
+            Body::Empty | Body::Segments(..) => return Ok(()),
+        };
+
+        let compressed = compress(&data, encoding, self.level(encoding).unwrap_or(6))?;
+        if compressed.len() >= data.len() {
+            // The file has been read so it can't be streamed anymore.
+            response.set_body(Body::Bytes(data));
+            return Ok(());
+        }
+        response.set_body(Body::Bytes(compressed));
+        set_encoding_headers(response, encoding);
+        Ok(())
+    }
+
+    /// Sets the headers `compress_response` would set on the response to a
+    /// `HEAD` request. The compressed length is found by compressing the body
+    /// without keeping the output, so a file body isn't read into memory. The
+    /// body is left as it is to be removed afterwards.
+    pub fn compress_head(&self, response: &mut Response, accept_encoding: Option<&str>) -> io::Result<()> {
+        let encoding = match self.response_encoding(response, accept_encoding) {
+            Some(encoding) => encoding,
+            None => return Ok(()),
+        };
+
+        let level = self.level(encoding).unwrap_or(6);
+        let length = response.content_length();
+        let compressed = match *response.body() {
+            Body::Text(ref text) => encode(text.as_bytes(), ByteCounter(0), encoding, level)?.0,
+            Body::Bytes(ref bytes) => encode(&bytes[..], ByteCounter(0), encoding, level)?.0,
+            Body::File(ref file, length) => {
+                let file: &File = file;
+                encode(file.take(length), ByteCounter(0), encoding, level)?.0
+            }
+            Body::Empty | Body::Segments(..) => return Ok(()),
+        };
+        if compressed >= length {
+            return Ok(());
+        }
+        if response.header("Content-Length").is_some() {
+            response.set_header("Content-Length", &compressed.to_string());
+        }
+        set_encoding_headers(response, encoding);
+        Ok(())
+    }
+
+    /// Returns the encoding to compress the response with. Responses that
+    /// could be compressed get `Vary: Accept-Encoding` whether or not the
+    /// client accepts an encoding.
+    fn response_encoding(&self, response: &mut Response, accept_encoding: Option<&str>) -> Option<Encoding> {
+        let compressible = match *response.body() {
+            Body::Text(_) | Body::Bytes(_) | Body::File(..) => {
+                self.should_compress(response.status(), response.content_length(),
+                                     response.header("Content-Type"),
+                                     response.header("Content-Encoding"),
+                                     response.header("Cache-Control"))
+            }
+            Body::Empty | Body::Segments(..) => false,
+        };
+        if !compressible {
+            return None;
+        }
+
+        let vary = merge_vary(response.header("Vary"));
+        response.set_header("Vary", &vary);
+        self.negotiate(accept_encoding)
+    }
+
+    /// Compresses the body of a response created by the application. The
+    /// response is returned as is if it isn't compressed.
+    pub fn compress_raw(&self, response: Vec<u8>, accept_encoding: Option<&str>) -> Vec<u8> {
+        let head_end = match response.windows(4).position(|w| w == b"\r\n\r\n") {
+            Some(i) => i,
+            None => return response,
+        };
+        let (head, body) = {
+            let head = String::from_utf8_lossy(&response[..head_end]).into_owned();
+            (head, &response[head_end + 4..])
+        };
+        let mut lines = head.split("\r\n");
+        let status_line = lines.next().unwrap_or("");
+        let fields: Vec<(&str, &str)> = lines
+            .filter_map(|line| {
+                let colon = line.find(':')?;
+                Some((line[..colon].trim(), line[colon + 1..].trim()))
+            })
+            .collect();
+        let field = |name: &str| {
+            fields.iter().find(|&&(n, _)| n.eq_ignore_ascii_case(name)).map(|&(_, v)| v)
+        };
+
+        let status = status_line.split(' ').nth(1)
+            .and_then(|code| code.parse().ok())
+            .map_or(StatusCode::INTERNAL_SERVER_ERROR, StatusCode);
+        let compressible = self.should_compress(status, body.len() as u64, field("Content-Type"),
+                                                field("Content-Encoding"), field("Cache-Control"));
+        if !compressible {
+            return response;
+        }
+
+        let encoding = self.negotiate(accept_encoding);
+        let compressed = encoding
+            .and_then(|e| compress(body, e, self.level(e).unwrap_or(6)).ok())
+            .filter(|compressed| compressed.len() < body.len());
+
+        let mut result = status_line.to_string();
+        for &(name, value) in &fields {
+            let replaced = name.eq_ignore_ascii_case("Vary")
+                || (compressed.is_some() && name.eq_ignore_ascii_case("Content-Length"));
+            if !replaced {
+                result.push_str(&format!("\r\n{}: {}", name, value));
+            }
+        }
+        result.push_str(&format!("\r\nVary: {}", merge_vary(field("Vary"))));
+        let body = match (encoding, compressed) {
+            (Some(encoding), Some(compressed)) => {
+                result.push_str(&format!("\r\nContent-Encoding: {}", encoding.name()));
+                result.push_str(&format!("\r\nContent-Length: {}", compressed.len()));
+                compressed
+            }
+            _ => body.to_vec(),
+        };
+        result.push_str("\r\n\r\n");
+
+        let mut result = result.into_bytes();
+        result.extend_from_slice(&body);
+        result
+    }
+
+    fn should_compress(&self,
+                       status: StatusCode,
+                       length: u64,
+                       content_type: Option<&str>,
+                       content_encoding: Option<&str>,
+                       cache_control: Option<&str>) -> bool {
+        let no_transform = cache_control.map_or(false, |c| {
+            c.split(',').any(|d| d.trim().eq_ignore_ascii_case("no-transform"))
+        });
+        status.allows_body()
+            && status != StatusCode::PARTIAL_CONTENT
+            && length >= self.min_size.unwrap_or(DEFAULT_MIN_SIZE)
+            && length <= MAX_COMPRESS_SIZE
+            && content_type.map_or(false, is_compressible)
+            && content_encoding.is_none()
+            && !no_transform
+    }
+}
+
+/// Chooses the available encoding with the highest q-value in an
+/// `Accept-Encoding` header. Ties are broken by the server's preference.
+pub fn negotiate(accept_encoding: Option<&str>, available: &[Encoding]) -> Option<Encoding> {
+    let accepted = parse_accept_encoding(accept_encoding?);
+    let qvalue = |encoding: Encoding| {
+        accepted.iter()
+            .find(|&&(ref coding, _)| encoding.matches(coding))
+            .or_else(|| accepted.iter().find(|&&(ref coding, _)| coding == "*"))
+            .map_or(0.0, |&(_, q)| q)
+    };
+
+    let mut best: Option<(Encoding, f32)> = None;
+    for &encoding in PREFERENCE.iter().filter(|e| available.contains(e)) {
+        let q = qvalue(encoding);
+        if q > 0.0 && best.map_or(true, |(_, best_q)| q > best_q) {
+            best = Some((encoding, q));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+/// Checks if a content type is text based and worth compressing. Images,
+/// audio, video and archives are already compressed.
+pub fn is_compressible(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or("").trim().to_lowercase();
+    essence.starts_with("text/")
+        || essence.ends_with("+json")
+        || essence.ends_with("+xml")
+        || essence == "application/javascript"
+        || essence == "application/json"
+        || essence == "application/xml"
+        || essence == "application/wasm"
+}
+
+/// Compresses the data with the encoding at the level.
+pub fn compress(data: &[u8], encoding: Encoding, level: u32) -> io::Result<Vec<u8>> {
+    encode(data, Vec::new(), encoding, level)
+}
+
+/// Compresses everything read from `data` into `out`, returning `out`.
+fn encode<R: Read, W: Write>(mut data: R, out: W, encoding: Encoding, level: u32) -> io::Result<W> {
+    match encoding {
+        Encoding::Brotli => {
+            let mut encoder = brotli::CompressorWriter::new(out, 4096, level.min(11), 22);
+            io::copy(&mut data, &mut encoder)?;
+            Ok(encoder.into_inner())
+        }
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(out, FlateLevel::new(level.min(9)));
+            io::copy(&mut data, &mut encoder)?;
+            encoder.finish()
+        }
+        Encoding::Deflate => {
+            let mut encoder = ZlibEncoder::new(out, FlateLevel::new(level.min(9)));
+            io::copy(&mut data, &mut encoder)?;
+            encoder.finish()
+        }
+    }
+}
+
+/// A writer that only counts the bytes written to it.
+struct ByteCounter(u64);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Marks the response as compressed with the encoding. The `ETag` is made
+/// weak since the bytes differ from the original.
+fn set_encoding_headers(response: &mut Response, encoding: Encoding) {
+    response.set_header("Content-Encoding", encoding.name());
+    response.weaken_etag();
+}
+
+/// Parses an `Accept-Encoding` header into codings and their q-values.
+fn parse_accept_encoding(value: &str) -> Vec<(String, f32)> {
+    value.split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let coding = parts.next()?.trim().to_lowercase();
+            if coding.is_empty() {
+                return None;
+            }
+            let q = parts
+                .filter_map(|p| {
+                    let p = p.trim();
+                    if p.len() > 2 && p[..2].eq_ignore_ascii_case("q=") {
+                        p[2..].parse::<f32>().ok()
+                    } else {
+                        None
+                    }
+                })
+                .next()
+                .unwrap_or(1.0);
+            Some((coding, q))
+        })
+        .collect()
+}
+
+/// Adds `Accept-Encoding` to the fields of a `Vary` header.
+pub fn merge_vary(vary: Option<&str>) -> String {
+    match vary {
+        Some(vary) if vary.split(',').any(|f| {
+            let f = f.trim();
+            f == "*" || f.eq_ignore_ascii_case("Accept-Encoding")
+        }) => vary.to_string(),
+        Some(vary) if !vary.trim().is_empty() => format!("{}, Accept-Encoding", vary),
+        _ => "Accept-Encoding".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+
+    use self::flate2::read::GzDecoder;
+    use self::tempdir::TempDir;
+
+    #[test]
+    fn test_negotiate() {
+        let compression = Compression::default();
+
+        assert_eq!(None, compression.negotiate(None));
+        assert_eq!(Some(Encoding::Brotli), compression.negotiate(Some("gzip, deflate, br")));
+        assert_eq!(Some(Encoding::Gzip), compression.negotiate(Some("br;q=0.5, gzip;q=0.8")));
+        assert_eq!(Some(Encoding::Gzip), compression.negotiate(Some("x-gzip")));
+        assert_eq!(Some(Encoding::Deflate), compression.negotiate(Some("*;q=0.1, deflate")));
+        assert_eq!(Some(Encoding::Brotli), compression.negotiate(Some("*")));
+        assert_eq!(None, compression.negotiate(Some("gzip;q=0, identity")));
+
+        let gzip_only = Compression { gzip: Some(9), deflate: None, brotli: None, min_size: None };
+        assert_eq!(Some(Encoding::Gzip), gzip_only.negotiate(Some("br, gzip;q=0.1")));
+        assert_eq!(None, gzip_only.negotiate(Some("br")));
+    }
+
+    #[test]
+    fn test_compress_response() {
+        let body = "console.log('hello');\n".repeat(100);
+        let mut response = Response::http_ok(body.clone());
+        response.add_response_headers("Content-Type: application/javascript; charset=utf-8");
+        response.add_response_headers("ETag: \"abc\"");
+        response.add_response_headers("Vary: Origin");
+
+        Compression::default().compress_response(&mut response, Some("gzip")).unwrap();
+
+        assert_eq!(Some("gzip"), response.header("Content-Encoding"));
+        assert_eq!(Some("Origin, Accept-Encoding"), response.header("Vary"));
+        assert_eq!(Some("W/\"abc\""), response.header("ETag"));
+        let length = response.content_length();
+        assert_eq!(Some(length.to_string().as_str()), response.header("Content-Length"));
+
+        let mut decoded = String::new();
+        match *response.body() {
+            Body::Bytes(ref bytes) => GzDecoder::new(&bytes[..]).read_to_string(&mut decoded).unwrap(),
+            _ => panic!("Body wasn't compressed"),
+        };
+        assert_eq!(body, decoded);
+    }
+
+    #[test]
+    fn test_compress_head_keeps_file_body() {
+        let dir = TempDir::new("rhs-tests").unwrap();
+        let path = dir.path().join("app.js");
+        let body = "console.log('hello');\n".repeat(100);
+        File::create(&path).unwrap().write_all(body.as_bytes()).unwrap();
+        let mut response = Response::http_ok_file(File::open(&path).unwrap(), body.len() as u64,
+                                                  "application/javascript");
+
+        Compression::default().compress_head(&mut response, Some("gzip")).unwrap();
+
+        let compressed = compress(body.as_bytes(), Encoding::Gzip, 6).unwrap();
+        assert_eq!(Some("gzip"), response.header("Content-Encoding"));
+        assert_eq!(Some(compressed.len().to_string().as_str()), response.header("Content-Length"));
+        assert!(match *response.body() { Body::File(..) => true, _ => false });
+    }
+
+    #[test]
+    fn test_small_and_binary_responses_are_not_compressed() {
+        let mut small = Response::http_ok("hello".to_string());
+        small.add_response_headers("Content-Type: text/plain");
+        Compression::default().compress_response(&mut small, Some("gzip")).unwrap();
+        assert_eq!(None, small.header("Content-Encoding"));
+        assert_eq!(None, small.header("Vary"));
+
+        let mut image = Response::new_bytes(vec![0; 4096], None, StatusCode::OK);
+        image.add_response_headers("Content-Type: image/png");
+        Compression::default().compress_response(&mut image, Some("gzip")).unwrap();
+        assert_eq!(None, image.header("Content-Encoding"));
+    }
+
+    #[test]
+    fn test_compress_raw() {
+        let body = "<p>hello</p>".repeat(200);
+        let raw = format!("HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\n\r\n{}",
+                          body.len(), body);
+
+        let compressed = Compression::default().compress_raw(raw.clone().into_bytes(), Some("deflate"));
+        let head = String::from_utf8_lossy(&compressed[..compressed.len() - 20]).into_owned();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nVary: Accept-Encoding"));
+        assert!(head.contains("Content-Encoding: deflate\r\n"));
+        assert!(compressed.len() < raw.len());
+
+        let identity = Compression::default().compress_raw(raw.clone().into_bytes(), None);
+        assert!(String::from_utf8(identity).unwrap().contains("Vary: Accept-Encoding\r\n\r\n<p>"));
+    }
+}
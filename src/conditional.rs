@@ -0,0 +1,122 @@
+//! Conditional requests (RFC 7232).
+
+use std::fs::Metadata;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use headers::Headers;
+use utils::date::{parse_http_date, unix_seconds};
+
+/// The outcome of evaluating the preconditions of a request.
+#[derive(Debug, PartialEq)]
+pub enum Precondition {
+    /// The request should be handled normally.
+    Proceed,
+    /// The client's cached copy is current (304).
+    NotModified,
+    /// A precondition failed (412).
+    Failed,
+}
+
+/// Creates a strong entity tag for a file from its size and modification
+/// time, so it changes whenever the file is written.
+pub fn file_etag(metadata: &Metadata) -> String {
+    let modified = metadata.modified().ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    format!("\"{:x}-{:x}{:08x}\"", metadata.len(), modified.as_secs(), modified.subsec_nanos())
+}
+
+/// Evaluates the `If-Match`, `If-Unmodified-Since`, `If-None-Match` and
+/// `If-Modified-Since` headers in the order given by RFC 7232 6. `safe` is
+/// true for `GET` and `HEAD` requests, which receive 304 rather than 412 when
+/// `If-None-Match` matches.
+pub fn evaluate(headers: &Headers, safe: bool, etag: &str, last_modified: Option<SystemTime>) -> Precondition {
+    if let Some(if_match) = headers.get("if-match") {
+        if !matches_any(if_match, etag, strong_match) {
+            return Precondition::Failed;
+        }
+    } else if let Some(since) = headers.get("if-unmodified-since").and_then(parse_http_date) {
+        if last_modified.map_or(false, |m| unix_seconds(m) > unix_seconds(since)) {
+            return Precondition::Failed;
+        }
+    }
+
+    if let Some(if_none_match) = headers.get("if-none-match") {
+        if matches_any(if_none_match, etag, weak_match) {
+            return if safe { Precondition::NotModified } else { Precondition::Failed };
+        }
+    } else if safe {
+        if let Some(since) = headers.get("if-modified-since").and_then(parse_http_date) {
+            if last_modified.map_or(false, |m| unix_seconds(m) <= unix_seconds(since)) {
+                return Precondition::NotModified;
+            }
+        }
+    }
+
+    Precondition::Proceed
+}
+
+/// Checks a comma separated list of entity tags, or `*`, against `etag`.
+fn matches_any(list: &str, etag: &str, compare: fn(&str, &str) -> bool) -> bool {
+    list.trim() == "*" || list.split(',').any(|tag| compare(tag.trim(), etag))
+}
+
+/// Both tags must be strong and identical (RFC 7232 2.3.2).
+fn strong_match(a: &str, b: &str) -> bool {
+    !a.starts_with("W/") && !b.starts_with("W/") && a == b
+}
+
+/// The tags must be identical ignoring any weakness indicator.
+fn weak_match(a: &str, b: &str) -> bool {
+    a.trim_start_matches("W/") == b.trim_start_matches("W/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ETAG: &str = "\"a-5f5e1000\"";
+
+    fn headers(lines: &[&str]) -> Headers {
+        Headers::parse(lines.iter().cloned()).unwrap()
+    }
+
+    fn modified() -> Option<SystemTime> {
+        parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT")
+    }
+
+    #[test]
+    fn test_if_none_match() {
+        let evaluate = |lines: &[&str], safe| evaluate(&headers(lines), safe, ETAG, modified());
+
+        assert_eq!(Precondition::Proceed, evaluate(&[], true));
+        assert_eq!(Precondition::NotModified, evaluate(&["If-None-Match: \"other\", W/\"a-5f5e1000\""], true));
+        assert_eq!(Precondition::NotModified, evaluate(&["If-None-Match: *"], true));
+        assert_eq!(Precondition::Failed, evaluate(&["If-None-Match: *"], false));
+        assert_eq!(Precondition::Proceed, evaluate(&["If-None-Match: \"other\""], true));
+        // If-Modified-Since is ignored when If-None-Match is sent.
+        assert_eq!(Precondition::Proceed, evaluate(&["If-None-Match: \"other\"",
+                                                     "If-Modified-Since: Sun, 06 Nov 1994 08:49:37 GMT"], true));
+    }
+
+    #[test]
+    fn test_if_modified_since() {
+        let evaluate = |lines: &[&str], safe| evaluate(&headers(lines), safe, ETAG, modified());
+
+        assert_eq!(Precondition::NotModified, evaluate(&["If-Modified-Since: Sun, 06 Nov 1994 08:49:37 GMT"], true));
+        assert_eq!(Precondition::Proceed, evaluate(&["If-Modified-Since: Sun, 06 Nov 1994 08:49:36 GMT"], true));
+        assert_eq!(Precondition::Proceed, evaluate(&["If-Modified-Since: Sun, 06 Nov 1994 08:49:37 GMT"], false));
+        assert_eq!(Precondition::Proceed, evaluate(&["If-Modified-Since: not a date"], true));
+    }
+
+    #[test]
+    fn test_if_match_and_if_unmodified_since() {
+        let evaluate = |lines: &[&str]| evaluate(&headers(lines), false, ETAG, modified());
+
+        assert_eq!(Precondition::Proceed, evaluate(&["If-Match: \"a-5f5e1000\""]));
+        assert_eq!(Precondition::Failed, evaluate(&["If-Match: W/\"a-5f5e1000\""]));
+        assert_eq!(Precondition::Failed, evaluate(&["If-Match: \"other\""]));
+        assert_eq!(Precondition::Failed, evaluate(&["If-Unmodified-Since: Sun, 06 Nov 1994 08:49:36 GMT"]));
+        assert_eq!(Precondition::Proceed, evaluate(&["If-Unmodified-Since: Sun, 06 Nov 1994 08:49:37 GMT"]));
+    }
+}
@@ -5,6 +5,12 @@ use std::fs::File;
 use std::io::prelude::*;
 use std::io::Error;
 use std::default::Default;
+use std::collections::HashMap;
+
+use cache::CacheRule;
+use compression::Compression;
+use mount::Mount;
+use routing::TrailingSlash;
 
 /// Server configuration. This is a finalized version of the server configuration.
 /// This will be consumed during initialization by `Server`.
@@ -17,6 +23,21 @@ pub struct Config {
     pub https_cert: Option<String>,
     pub cert_password: Option<String>,
     pub threads: Option<usize>,
+    pub max_body_size: Option<usize>,
+    pub keep_alive_timeout: Option<u64>,
+    pub max_requests_per_connection: Option<usize>,
+    pub shutdown_grace_period: Option<u64>,
+    pub mime_types: Option<HashMap<String, String>>,
+    pub cache_rules: Option<Vec<CacheRule>>,
+    pub compression: Option<Compression>,
+    pub autoindex: Option<bool>,
+    pub index_files: Option<Vec<String>>,
+    pub trailing_slash: Option<TrailingSlash>,
+    pub spa_fallback: Option<String>,
+    pub watch: Option<bool>,
+    pub watch_poll_interval: Option<u64>,
+    pub dev: Option<bool>,
+    pub mounts: Option<Vec<Mount>>,
 }
 
 /// Server configuration implementation of the builder pattern. This struct allows chain
@@ -30,6 +51,21 @@ pub struct ConfigBuilder {
     pub https_cert: Option<String>,
     pub cert_password: Option<String>,
     pub threads: Option<usize>,
+    pub max_body_size: Option<usize>,
+    pub keep_alive_timeout: Option<u64>,
+    pub max_requests_per_connection: Option<usize>,
+    pub shutdown_grace_period: Option<u64>,
+    pub mime_types: Option<HashMap<String, String>>,
+    pub cache_rules: Option<Vec<CacheRule>>,
+    pub compression: Option<Compression>,
+    pub autoindex: Option<bool>,
+    pub index_files: Option<Vec<String>>,
+    pub trailing_slash: Option<TrailingSlash>,
+    pub spa_fallback: Option<String>,
+    pub watch: Option<bool>,
+    pub watch_poll_interval: Option<u64>,
+    pub dev: Option<bool>,
+    pub mounts: Option<Vec<Mount>>,
 }
 
 impl ConfigBuilder {
@@ -59,7 +95,6 @@ impl ConfigBuilder {
         self
     }
 
-    #[allow(dead_code)]
     pub fn set_static_folder(&mut self, static_folder: &str) -> &mut Self {
         self.static_folder = Some(static_folder.to_string());
         self
@@ -80,6 +115,103 @@ impl ConfigBuilder {
         self
     }
 
+    pub fn set_max_body_size(&mut self, max_body_size: usize) -> &mut Self {
+        self.max_body_size = Some(max_body_size);
+        self
+    }
+
+    pub fn set_keep_alive_timeout(&mut self, keep_alive_timeout: u64) -> &mut Self {
+        self.keep_alive_timeout = Some(keep_alive_timeout);
+        self
+    }
+
+    pub fn set_max_requests_per_connection(&mut self, max_requests: usize) -> &mut Self {
+        self.max_requests_per_connection = Some(max_requests);
+        self
+    }
+
+    pub fn set_shutdown_grace_period(&mut self, grace_period: u64) -> &mut Self {
+        self.shutdown_grace_period = Some(grace_period);
+        self
+    }
+
+    /// Adds a content type for files with the extension, overriding the built
+    /// in content type if there is one.
+    pub fn add_mime_type(&mut self, extension: &str, content_type: &str) -> &mut Self {
+        self.mime_types.get_or_insert_with(HashMap::new)
+            .insert(extension.to_string(), content_type.to_string());
+        self
+    }
+
+    /// Adds a caching rule for static files. Rules are checked in the order
+    /// they are added and the first matching rule is used.
+    pub fn add_cache_rule(&mut self, rule: CacheRule) -> &mut Self {
+        self.cache_rules.get_or_insert_with(Vec::new).push(rule);
+        self
+    }
+
+    /// Sets the response compression algorithms and levels. Responses are
+    /// compressed with `Compression::default()` if this isn't set.
+    pub fn set_compression(&mut self, compression: Compression) -> &mut Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Serves listing pages for the static directories.
+    pub fn set_autoindex(&mut self, autoindex: bool) -> &mut Self {
+        self.autoindex = Some(autoindex);
+        self
+    }
+
+    /// Sets the files served for a directory, in order of preference.
+    pub fn set_index_files(&mut self, index_files: Vec<String>) -> &mut Self {
+        self.index_files = Some(index_files);
+        self
+    }
+
+    /// Sets how paths that only match a route with their trailing slash added
+    /// or removed are handled.
+    pub fn set_trailing_slash(&mut self, trailing_slash: TrailingSlash) -> &mut Self {
+        self.trailing_slash = Some(trailing_slash);
+        self
+    }
+
+    /// Sets the file served for page requests matching no route, e.g. the
+    /// `index.html` of a single page application. With an `app` it is served
+    /// when the application answers them with a 404.
+    pub fn set_spa_fallback(&mut self, file: &str) -> &mut Self {
+        self.spa_fallback = Some(file.to_string());
+        self
+    }
+
+    /// Updates the static routes while the server runs as files are added
+    /// and removed.
+    pub fn set_watch(&mut self, watch: bool) -> &mut Self {
+        self.watch = Some(watch);
+        self
+    }
+
+    /// Polls the static folders for changes every `interval` milliseconds
+    /// instead of using inotify.
+    pub fn set_watch_poll_interval(&mut self, interval: u64) -> &mut Self {
+        self.watch_poll_interval = Some(interval);
+        self
+    }
+
+    /// Development mode: watches the static folders and reloads pages in the
+    /// browser when files change.
+    pub fn set_dev(&mut self, dev: bool) -> &mut Self {
+        self.dev = Some(dev);
+        self
+    }
+
+    /// Adds a directory served at a URL prefix whose files are found when
+    /// requested, see `Mount`.
+    pub fn add_mount(&mut self, mount: Mount) -> &mut Self {
+        self.mounts.get_or_insert_with(Vec::new).push(mount);
+        self
+    }
+
     /// Finalize the `Config`.
     pub fn build(self) -> Config {
         Config {
@@ -90,6 +222,21 @@ impl ConfigBuilder {
             https_cert: self.https_cert,
             cert_password: self.cert_password,
             threads: self.threads,
+            max_body_size: self.max_body_size,
+            keep_alive_timeout: self.keep_alive_timeout,
+            max_requests_per_connection: self.max_requests_per_connection,
+            shutdown_grace_period: self.shutdown_grace_period,
+            mime_types: self.mime_types,
+            cache_rules: self.cache_rules,
+            compression: self.compression,
+            autoindex: self.autoindex,
+            index_files: self.index_files,
+            trailing_slash: self.trailing_slash,
+            spa_fallback: self.spa_fallback,
+            watch: self.watch,
+            watch_poll_interval: self.watch_poll_interval,
+            dev: self.dev,
+            mounts: self.mounts,
         }
     }
 }
@@ -104,6 +251,21 @@ impl Default for ConfigBuilder {
             https_cert: None,
             cert_password: None,
             threads: None,
+            max_body_size: None,
+            keep_alive_timeout: None,
+            max_requests_per_connection: None,
+            shutdown_grace_period: None,
+            mime_types: None,
+            cache_rules: None,
+            compression: None,
+            autoindex: None,
+            index_files: None,
+            trailing_slash: None,
+            spa_fallback: None,
+            watch: None,
+            watch_poll_interval: None,
+            dev: None,
+            mounts: None,
         }
     }
 }
@@ -111,7 +273,6 @@ impl Default for ConfigBuilder {
 impl Config {
 
     /// Create a config from a JSON string. Only used in testing currently.
-    #[allow(dead_code)]
     pub fn from_json(json: &str) -> Config {
         serde_json::from_str(json).unwrap()
     }
@@ -0,0 +1,17 @@
+//! Native Rust request handlers.
+
+use request::Request;
+use response::Response;
+
+/// Creates the response for a request to a route registered with
+/// `Server::route`. Implemented for any closure taking a `&Request` and
+/// returning a `Response`.
+pub trait Handler: Send + Sync {
+    fn handle(&self, request: &Request) -> Response;
+}
+
+impl<F> Handler for F where F: Fn(&Request) -> Response + Send + Sync {
+    fn handle(&self, request: &Request) -> Response {
+        self(request)
+    }
+}
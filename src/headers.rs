@@ -0,0 +1,208 @@
+//! HTTP header fields.
+
+use std::error::Error;
+use std::fmt;
+
+/// The maximum number of header fields accepted in a single message.
+pub const MAX_HEADER_COUNT: usize = 100;
+
+/// The maximum size in bytes of a single header field line.
+pub const MAX_HEADER_LINE_SIZE: usize = 8192;
+
+/// A case-insensitive, multi-valued map of header fields. Fields are kept in
+/// the order they were received so repeated fields (e.g. `Cookie`) can be
+/// retrieved in full.
+#[derive(Debug, Clone, Default)]
+pub struct Headers {
+    fields: Vec<(String, String)>,
+}
+
+/// Errors that can occur while parsing a header section.
+#[derive(Debug, PartialEq)]
+pub enum HeaderError {
+    /// A line began with whitespace (obsolete line folding, RFC 7230 3.2.4).
+    ObsoleteLineFolding,
+    /// A line did not contain a colon separating the name and value.
+    MissingColon,
+    /// The field name was empty, contained a non token character or was
+    /// followed by whitespace before the colon.
+    InvalidName(String),
+    /// The field value contained a control character.
+    InvalidValue(String),
+    /// More than `MAX_HEADER_COUNT` fields were sent.
+    TooManyFields,
+    /// A field line exceeded `MAX_HEADER_LINE_SIZE` bytes.
+    LineTooLong,
+}
+
+impl Headers {
+    /// Creates an empty header map.
+    pub fn new() -> Headers {
+        Headers {
+            fields: Vec::new(),
+        }
+    }
+
+    /// Parses the header section of a message. Each line must be a single
+    /// `name: value` field; the section ends at the first empty line.
+    pub fn parse<'a, I>(lines: I) -> Result<Headers, HeaderError>
+        where I: IntoIterator<Item = &'a str>
+    {
+        let mut headers = Headers::new();
+
+        for line in lines {
+            let line = line.trim_end_matches('\r');
+            if line.is_empty() {
+                break;
+            }
+            if line.len() > MAX_HEADER_LINE_SIZE {
+                return Err(HeaderError::LineTooLong);
+            }
+            if line.starts_with(' ') || line.starts_with('\t') {
+                return Err(HeaderError::ObsoleteLineFolding);
+            }
+            if headers.len() == MAX_HEADER_COUNT {
+                return Err(HeaderError::TooManyFields);
+            }
+
+            let colon = line.find(':').ok_or(HeaderError::MissingColon)?;
+            let name = &line[..colon];
+            let value = line[colon + 1..].trim_matches(|c| c == ' ' || c == '\t');
+
+            if name.is_empty() || !name.bytes().all(is_token) {
+                return Err(HeaderError::InvalidName(name.to_string()));
+            }
+            if value.chars().any(|c| c.is_control() && c != '\t') {
+                return Err(HeaderError::InvalidValue(name.to_string()));
+            }
+
+            headers.append(name, value);
+        }
+
+        Ok(headers)
+    }
+
+    /// Returns the first value of the field `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields.iter()
+            .find(|&&(ref n, _)| n.eq_ignore_ascii_case(name))
+            .map(|&(_, ref v)| v.as_str())
+    }
+
+    /// Returns every value of the field `name` in the order they were received.
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.fields.iter()
+            .filter(|&&(ref n, _)| n.eq_ignore_ascii_case(name))
+            .map(|&(_, ref v)| v.as_str())
+            .collect()
+    }
+
+    /// Checks if the field `name` is present.
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Adds a value for the field `name`, keeping any existing values.
+    pub fn append(&mut self, name: &str, value: &str) {
+        self.fields.push((name.to_string(), value.to_string()));
+    }
+
+    /// Returns the number of fields, counting repeated fields individually.
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Iterates over `(name, value)` pairs in the order they were received.
+    pub fn iter(&self) -> ::std::slice::Iter<(String, String)> {
+        self.fields.iter()
+    }
+}
+
+/// Checks if the byte is a `tchar` as defined by RFC 7230 3.2.6.
+fn is_token(b: u8) -> bool {
+    match b {
+        b'!' | b'#' | b'$' | b'%' | b'&' | b'\'' | b'*' | b'+' | b'-' | b'.' |
+        b'^' | b'_' | b'`' | b'|' | b'~' => true,
+        _ => b.is_ascii_alphanumeric(),
+    }
+}
+
+impl HeaderError {
+    fn message(&self) -> &str {
+        match *self {
+            HeaderError::ObsoleteLineFolding => "Obsolete line folding in header",
+            HeaderError::MissingColon => "Header line without a colon",
+            HeaderError::InvalidName(_) => "Invalid header name",
+            HeaderError::InvalidValue(_) => "Invalid header value",
+            HeaderError::TooManyFields => "Too many header fields",
+            HeaderError::LineTooLong => "Header line too long",
+        }
+    }
+}
+
+impl Error for HeaderError {
+    fn description(&self) -> &str {
+        self.message()
+    }
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HeaderError::InvalidName(ref n) | HeaderError::InvalidValue(ref n) => {
+                write!(f, "{}: {:?}", self.message(), n)
+            }
+            _ => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_is_case_insensitive_and_multi_valued() {
+        let lines = vec!["Host: localhost", "Cookie: a=1", "content-type: text/plain", "COOKIE: b=2"];
+        let headers = Headers::parse(lines).unwrap();
+
+        assert_eq!(Some("localhost"), headers.get("host"));
+        assert_eq!(Some("text/plain"), headers.get("Content-Type"));
+        assert_eq!(vec!["a=1", "b=2"], headers.get_all("cookie"));
+        assert_eq!(4, headers.len());
+    }
+
+    #[test]
+    fn test_values_are_trimmed() {
+        let headers = Headers::parse(vec!["Accept:  */*\t\r", "", "Ignored: after blank line"]).unwrap();
+
+        assert_eq!(Some("*/*"), headers.get("accept"));
+        assert!(!headers.contains("ignored"));
+    }
+
+    #[test]
+    fn test_malformed_headers_are_rejected() {
+        assert_eq!(Some(HeaderError::ObsoleteLineFolding),
+                   Headers::parse(vec!["X-Long: a", " continued"]).err());
+        assert_eq!(Some(HeaderError::MissingColon),
+                   Headers::parse(vec!["NoColon"]).err());
+        assert!(Headers::parse(vec!["Host : localhost"]).is_err());
+        assert!(Headers::parse(vec!["Bad\u{0}: value"]).is_err());
+        assert!(Headers::parse(vec!["X-Value: a\u{1}b"]).is_err());
+    }
+
+    #[test]
+    fn test_header_limits() {
+        let many: Vec<String> = (0..MAX_HEADER_COUNT + 1).map(|i| format!("X-{}: {}", i, i)).collect();
+        let result = Headers::parse(many.iter().map(|s| s.as_str()));
+        assert_eq!(Some(HeaderError::TooManyFields), result.err());
+
+        let long = format!("X-Long: {}", "a".repeat(MAX_HEADER_LINE_SIZE));
+        let result = Headers::parse(vec![long.as_str()]);
+        assert_eq!(Some(HeaderError::LineTooLong), result.err());
+    }
+}
@@ -0,0 +1,66 @@
+//! #rust-http-server
+//!
+//! An HTTP server written in rust. It can be run as a binary or embedded as a
+//! library, serving static files alongside Rust `Handler`s:
+//!
+//! ```no_run
+//! extern crate rust_http_server;
+//!
+//! use rust_http_server::{ConfigBuilder, Request, Response, Server};
+//!
+//! fn main() {
+//!     let mut config = ConfigBuilder::default();
+//!     config.set_port("8080");
+//!
+//!     let mut server = Server::from_config(config.build());
+//!     server.route("/hello", |request: &Request| {
+//!         Response::http_ok(format!("Hello {}", request.query("name").unwrap_or("world")))
+//!     });
+//!     server.serve();
+//! }
+//! ```
+#[macro_use] extern crate serde_derive;
+#[macro_use] extern crate log;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[macro_use]
+mod utils;
+
+pub mod server;
+mod threadpool;
+pub mod config;
+pub mod response;
+pub mod request;
+pub mod headers;
+mod reader;
+mod mime;
+pub mod status;
+pub mod handler;
+pub mod range;
+pub mod cache;
+pub mod compression;
+pub mod mount;
+mod autoindex;
+mod conditional;
+mod routing;
+mod watcher;
+mod livereload;
+//#[cfg(feature="wsgi")]
+mod wsgi;
+
+pub use config::{Config, ConfigBuilder};
+pub use handler::Handler;
+pub use request::{Request, RequestKind};
+pub use response::Response;
+pub use routing::TrailingSlash;
+pub use server::{Server, ShutdownHandle};
+pub use status::StatusCode;
+
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// Turns printing requests and other debug output to stdout on or off, like
+/// the `-v` flag of the binary.
+pub fn set_verbose(verbose: bool) {
+    VERBOSE.store(verbose, Ordering::Relaxed);
+}
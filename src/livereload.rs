@@ -0,0 +1,173 @@
+//! Reloading pages in the browser when static files change, for development.
+//! HTML responses get a script that listens for `reload` events on
+//! `LIVERELOAD_PATH`, a Server-Sent Events stream.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use response::{Body, Response};
+use status::StatusCode;
+
+/// The path of the event stream.
+pub const LIVERELOAD_PATH: &str = "/__livereload";
+
+/// The script added to HTML pages.
+const SCRIPT: &str = r#"<script>
+(function () {
+    var source = new EventSource("/__livereload");
+    source.onmessage = function (event) {
+        if (event.data === "reload") {
+            window.location.reload();
+        }
+    };
+})();
+</script>
+"#;
+
+/// How long a write to a browser may block before it is disconnected.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The browsers listening for reload events.
+#[derive(Clone, Default)]
+pub struct LiveReload {
+    clients: Arc<Mutex<Vec<TcpStream>>>,
+}
+
+impl LiveReload {
+    pub fn new() -> LiveReload {
+        LiveReload::default()
+    }
+
+    /// Starts the event stream on the connection and keeps it to send reload
+    /// events to. The connection is no longer read from.
+    pub fn subscribe(&self, mut stream: TcpStream) -> io::Result<()> {
+        // Holding the lock until the client is added keeps a reload from
+        // being missed between the headers and the first event.
+        let mut clients = self.lock();
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        stream.write_all(b"HTTP/1.1 200 OK\r\n\
+                           Content-Type: text/event-stream\r\n\
+                           Cache-Control: no-cache\r\n\
+                           Connection: keep-alive\r\n\r\n\
+                           retry: 1000\n\n")?;
+        stream.flush()?;
+        clients.push(stream);
+        Ok(())
+    }
+
+    /// Sends a reload event to every browser. Browsers that have disconnected
+    /// are removed.
+    pub fn reload(&self) {
+        let mut clients = self.lock();
+        let count = clients.len();
+        let connected: Vec<TcpStream> = clients.drain(..)
+            .filter_map(|mut client| client.write_all(b"data: reload\n\n").and_then(|_| client.flush()).ok().map(|_| client))
+            .collect();
+        debug!("Sent reload to {} of {} browsers", connected.len(), count);
+        *clients = connected;
+    }
+
+    /// Returns the number of browsers listening for reload events.
+    pub fn client_count(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Adds the reload script to a successful HTML response. The changed page
+    /// only gets a weak `ETag` and no `Accept-Ranges`, since ranges of it
+    /// can't be served.
+    pub fn inject(&self, response: &mut Response) -> io::Result<()> {
+        let is_html = response.header("Content-Type").map_or(false, |t| t.starts_with("text/html"));
+        if response.status() != StatusCode::OK || !is_html || response.header("Content-Encoding").is_some() {
+            return Ok(());
+        }
+
+        let html = match *response.body() {
+            Body::Text(ref text) => inject_script(text.as_bytes()),
+            Body::Bytes(ref bytes) => inject_script(bytes),
+            Body::File(ref file, length) => {
+                let mut html = Vec::with_capacity(length as usize);
+                let file: &File = file;
+                file.take(length).read_to_end(&mut html)?;
+                inject_script(&html)
+            }
+            Body::Empty | Body::Segments(..) => return Ok(()),
+        };
+        response.set_body(Body::Bytes(html));
+        response.weaken_etag();
+        response.remove_header("Accept-Ranges");
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<Vec<TcpStream>> {
+        self.clients.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Inserts the script before the closing `</body>` tag, or at the end of the
+/// page if there is none.
+pub fn inject_script(html: &[u8]) -> Vec<u8> {
+    let position = html.windows(7)
+        .rposition(|w| w.eq_ignore_ascii_case(b"</body>"))
+        .unwrap_or(html.len());
+    let mut result = Vec::with_capacity(html.len() + SCRIPT.len());
+    result.extend_from_slice(&html[..position]);
+    result.extend_from_slice(SCRIPT.as_bytes());
+    result.extend_from_slice(&html[position..]);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_inject_script() {
+        let html = inject_script(b"<html><BODY><p>hi</p></BODY></html>");
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.starts_with("<html><BODY><p>hi</p><script>"));
+        assert!(html.ends_with("</script>\n</BODY></html>"));
+
+        let fragment = String::from_utf8(inject_script(b"<p>hi</p>")).unwrap();
+        assert!(fragment.starts_with("<p>hi</p><script>"));
+    }
+
+    #[test]
+    fn test_inject_only_into_html() {
+        let live_reload = LiveReload::new();
+        let mut page = Response::http_ok("<body></body>".to_string());
+        page.add_response_headers("Content-Type: text/html; charset=utf-8");
+        page.add_response_headers("ETag: \"page\"");
+        page.add_response_headers("Accept-Ranges: bytes");
+        live_reload.inject(&mut page).unwrap();
+        assert!(page.to_string().contains("new EventSource(\"/__livereload\")"));
+        assert_eq!(Some("W/\"page\""), page.header("ETag"));
+        assert_eq!(None, page.header("Accept-Ranges"));
+
+        let mut script = Response::http_ok("</body>".to_string());
+        script.add_response_headers("Content-Type: application/javascript");
+        live_reload.inject(&mut script).unwrap();
+        assert!(!script.to_string().contains("EventSource"));
+    }
+
+    #[test]
+    fn test_reload_events() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let browser = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server_side, _) = listener.accept().unwrap();
+
+        let live_reload = LiveReload::new();
+        live_reload.subscribe(server_side).unwrap();
+        live_reload.reload();
+
+        let mut lines = BufReader::new(browser).lines().map(|l| l.unwrap());
+        assert_eq!("HTTP/1.1 200 OK", lines.next().unwrap());
+        assert!(lines.any(|l| l == "Content-Type: text/event-stream"));
+        assert!(lines.any(|l| l == "data: reload"));
+        assert_eq!(1, live_reload.client_count());
+    }
+}
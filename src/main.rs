@@ -2,30 +2,19 @@
 //!
 //! An HTTP server written in rust
 //! 
-#[macro_use] extern crate serde_derive;
+extern crate rust_http_server;
 extern crate pretty_env_logger;
 #[macro_use] extern crate log;
 extern crate ctrlc;
 extern crate clap;
 
-#[macro_use]
-mod utils;
-
-mod server;
-mod threadpool;
-mod config;
-mod response;
-mod request;
-mod routing;
-//#[cfg(feature="wsgi")]
-mod wsgi;
 mod cli;
 
 use std::process;
 
-use cli::{run_cli, config_from_cli, cli_verbosity, cli_serve_directory, cli_logging};
+use rust_http_server::Server;
 
-pub static mut VERBOSE: bool = false;
+use cli::{run_cli, config_from_cli, cli_verbosity, cli_serve_directory, cli_logging};
 
 fn main() {
     let cli = run_cli().get_matches();
@@ -36,18 +25,23 @@ fn main() {
     let name = env!("CARGO_PKG_NAME");
     println!("Running {}", name);
 
-    // Listen for keyboard interrupt here
-    ctrlc::set_handler(move || {
-        // TODO: Implement cleanup instead of just exiting
-        process::exit(0);
-    }).expect("Error setting Ctrl-c handler");
-    
     let config = config_from_cli(&cli);
 
-    let mut server = server::Server::from_config(config);
+    let mut server = Server::from_config(config);
 
     cli_serve_directory(&cli, &mut server);
 
+    // Listen for keyboard interrupt and termination signals here. A second
+    // signal exits without waiting for requests in flight.
+    let shutdown = server.shutdown_handle();
+    ctrlc::set_handler(move || {
+        if shutdown.is_stopping() {
+            process::exit(1);
+        }
+        info!("Shutting down");
+        shutdown.shutdown();
+    }).expect("Error setting Ctrl-c handler");
+
     server.serve();
 }
 
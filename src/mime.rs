@@ -0,0 +1,143 @@
+//! Content types for static files.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The content type used when the extension of a file is unknown.
+pub const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+/// The built in extension to content type table.
+static MIME_TYPES: &[(&str, &str)] = &[
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("css", "text/css"),
+    ("js", "application/javascript"),
+    ("mjs", "application/javascript"),
+    ("json", "application/json"),
+    ("map", "application/json"),
+    ("webmanifest", "application/manifest+json"),
+    ("txt", "text/plain"),
+    ("md", "text/markdown"),
+    ("csv", "text/csv"),
+    ("xml", "application/xml"),
+    ("rss", "application/rss+xml"),
+    ("atom", "application/atom+xml"),
+    ("yaml", "text/yaml"),
+    ("yml", "text/yaml"),
+    ("svg", "image/svg+xml"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("bmp", "image/bmp"),
+    ("ico", "image/x-icon"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    ("eot", "application/vnd.ms-fontobject"),
+    ("mp3", "audio/mpeg"),
+    ("m4a", "audio/mp4"),
+    ("ogg", "audio/ogg"),
+    ("oga", "audio/ogg"),
+    ("wav", "audio/wav"),
+    ("flac", "audio/flac"),
+    ("mp4", "video/mp4"),
+    ("webm", "video/webm"),
+    ("ogv", "video/ogg"),
+    ("mov", "video/quicktime"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+    ("wasm", "application/wasm"),
+];
+
+/// Maps file extensions to content types. Text types are given a utf-8 charset.
+#[derive(Clone, Debug)]
+pub struct MimeTypes {
+    types: HashMap<String, String>,
+}
+
+impl MimeTypes {
+    /// Creates the table of built in content types.
+    pub fn new() -> MimeTypes {
+        let mut mime_types = MimeTypes {
+            types: HashMap::new(),
+        };
+        for &(extension, content_type) in MIME_TYPES {
+            mime_types.insert(extension, content_type);
+        }
+        mime_types
+    }
+
+    /// Creates the table of built in content types with the provided mappings
+    /// added. These take precedence over the built in types.
+    pub fn with_overrides(overrides: &HashMap<String, String>) -> MimeTypes {
+        let mut mime_types = MimeTypes::new();
+        for (extension, content_type) in overrides {
+            mime_types.insert(extension, content_type);
+        }
+        mime_types
+    }
+
+    /// Adds or replaces the content type for an extension. The extension may be
+    /// given with or without a leading `.`.
+    pub fn insert(&mut self, extension: &str, content_type: &str) {
+        let extension = extension.trim_start_matches('.').to_lowercase();
+        self.types.insert(extension, with_charset(content_type));
+    }
+
+    /// Returns the content type for a file based on its extension.
+    pub fn for_path(&self, path: &Path) -> &str {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .and_then(|e| self.types.get(&e.to_lowercase()))
+            .map_or(DEFAULT_MIME_TYPE, |t| t.as_str())
+    }
+}
+
+/// Adds `charset=utf-8` to text based content types without a charset.
+fn with_charset(content_type: &str) -> String {
+    let essence = content_type.split(';').next().unwrap_or("").trim();
+    let is_text = essence.starts_with("text/")
+        || essence == "application/javascript"
+        || essence == "application/json"
+        || essence.ends_with("+json")
+        || essence.ends_with("+xml")
+        || essence == "application/xml";
+    if is_text && !content_type.to_lowercase().contains("charset=") {
+        format!("{}; charset=utf-8", content_type)
+    } else {
+        content_type.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_type_for_path() {
+        let mime_types = MimeTypes::new();
+
+        assert_eq!("application/javascript; charset=utf-8", mime_types.for_path(Path::new("static/app.js")));
+        assert_eq!("text/html; charset=utf-8", mime_types.for_path(Path::new("INDEX.HTML")));
+        assert_eq!("image/png", mime_types.for_path(Path::new("logo.png")));
+        assert_eq!(DEFAULT_MIME_TYPE, mime_types.for_path(Path::new("data.unknown")));
+        assert_eq!(DEFAULT_MIME_TYPE, mime_types.for_path(Path::new("Makefile")));
+    }
+
+    #[test]
+    fn test_overrides() {
+        let mut overrides = HashMap::new();
+        overrides.insert(".js".to_string(), "text/javascript".to_string());
+        overrides.insert("dat".to_string(), "application/x-custom".to_string());
+        let mime_types = MimeTypes::with_overrides(&overrides);
+
+        assert_eq!("text/javascript; charset=utf-8", mime_types.for_path(Path::new("app.js")));
+        assert_eq!("application/x-custom", mime_types.for_path(Path::new("file.dat")));
+    }
+}
@@ -0,0 +1,232 @@
+//! Static directories served from the file system as they are requested,
+//! instead of from routes registered at startup. Files added later are served
+//! right away and large trees don't cost startup time or memory.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use utils::url::percent_decode;
+
+/// How symbolic links within a mount are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymlinkPolicy {
+    /// Links are never followed.
+    Deny,
+    /// Links are followed if they lead to a path within the root.
+    Contained,
+    /// Links are followed wherever they lead.
+    Follow,
+}
+
+impl Default for SymlinkPolicy {
+    fn default() -> SymlinkPolicy {
+        SymlinkPolicy::Contained
+    }
+}
+
+/// A directory served at a URL prefix, e.g. `/assets` for `./public`. Request
+/// paths are resolved against `root` when requested. Hidden files are not
+/// served.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mount {
+    pub url: String,
+    pub root: String,
+    #[serde(default)]
+    pub symlinks: SymlinkPolicy,
+}
+
+/// A file or directory found for a request path.
+#[derive(Debug, PartialEq)]
+pub enum Resolved {
+    File(PathBuf),
+    Directory(PathBuf),
+}
+
+/// Errors that can occur while resolving a request path in a mount.
+#[derive(Debug, PartialEq)]
+pub enum ResolveError {
+    /// The path contained a `..` segment, an encoded `/` or `\` or a NUL byte.
+    InvalidPath,
+    /// The path led through a symbolic link the policy doesn't allow.
+    Forbidden,
+    NotFound,
+}
+
+impl Mount {
+    /// Creates a mount serving `root` at `url` that follows symbolic links
+    /// within the root.
+    pub fn new(url: &str, root: &str) -> Mount {
+        Mount {
+            url: url.to_string(),
+            root: root.to_string(),
+            symlinks: SymlinkPolicy::default(),
+        }
+    }
+
+    /// Returns the part of the path after the mount's URL, or `None` if the
+    /// path is not within the mount.
+    pub fn relative<'a>(&self, path: &'a str) -> Option<&'a str> {
+        let url = self.url.trim_end_matches('/');
+        if !path.starts_with(url) {
+            return None;
+        }
+        let rest = &path[url.len()..];
+        if rest.is_empty() || rest.starts_with('/') {
+            Some(rest)
+        } else {
+            None
+        }
+    }
+
+    /// Finds the file or directory for a request path, as sent by the client
+    /// before percent decoding so encoded separators can be told apart.
+    pub fn resolve(&self, raw_path: &str) -> Result<Resolved, ResolveError> {
+        let relative = self.relative(raw_path).ok_or(ResolveError::NotFound)?;
+        let segments = segments(relative)?;
+        let root = fs::canonicalize(&self.root).map_err(|_| ResolveError::NotFound)?;
+
+        let mut path = root.clone();
+        for segment in segments {
+            path.push(segment);
+            if self.symlinks == SymlinkPolicy::Deny {
+                let metadata = fs::symlink_metadata(&path).map_err(|_| ResolveError::NotFound)?;
+                if metadata.file_type().is_symlink() {
+                    return Err(ResolveError::Forbidden);
+                }
+            }
+        }
+        // Links can lead out of the root or to hidden files within it.
+        let path = fs::canonicalize(&path).map_err(|_| ResolveError::NotFound)?;
+        match path.strip_prefix(&root) {
+            Ok(relative) => {
+                if relative.components().any(|c| c.as_os_str().to_string_lossy().starts_with('.')) {
+                    return Err(ResolveError::NotFound);
+                }
+            }
+            Err(_) if self.symlinks != SymlinkPolicy::Follow => return Err(ResolveError::Forbidden),
+            Err(_) => {}
+        }
+
+        let metadata = fs::metadata(&path).map_err(|_| ResolveError::NotFound)?;
+        if metadata.is_file() {
+            Ok(Resolved::File(path))
+        } else if metadata.is_dir() {
+            Ok(Resolved::Directory(path))
+        } else {
+            Err(ResolveError::NotFound)
+        }
+    }
+}
+
+/// Splits a raw path into its percent decoded segments. Empty and `.`
+/// segments are skipped. Hidden files aren't found.
+fn segments(raw_path: &str) -> Result<Vec<String>, ResolveError> {
+    let mut segments = Vec::new();
+    for raw in raw_path.split('/') {
+        let lowercase = raw.to_ascii_lowercase();
+        if lowercase.contains("%2f") || lowercase.contains("%5c") {
+            return Err(ResolveError::InvalidPath);
+        }
+        let segment = percent_decode(raw);
+        if segment == ".." || segment.contains(&['\0', '\\'][..]) {
+            return Err(ResolveError::InvalidPath);
+        }
+        if segment.is_empty() || segment == "." {
+            continue;
+        }
+        if segment.starts_with('.') {
+            return Err(ResolveError::NotFound);
+        }
+        segments.push(segment);
+    }
+    Ok(segments)
+}
+
+impl ResolveError {
+    fn message(&self) -> &str {
+        match *self {
+            ResolveError::InvalidPath => "Invalid path",
+            ResolveError::Forbidden => "Symbolic link not allowed",
+            ResolveError::NotFound => "Not found",
+        }
+    }
+}
+
+impl Error for ResolveError {
+    fn description(&self) -> &str {
+        self.message()
+    }
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+    use std::fs::{create_dir, File};
+    use self::tempdir::TempDir;
+
+    #[test]
+    fn test_relative() {
+        let mount = Mount::new("/assets/", "public");
+        assert_eq!(Some("/app.js"), mount.relative("/assets/app.js"));
+        assert_eq!(Some(""), mount.relative("/assets"));
+        assert_eq!(None, mount.relative("/assetsx/app.js"));
+        assert_eq!(Some("/app.js"), Mount::new("/", "public").relative("/app.js"));
+    }
+
+    #[test]
+    fn test_resolve_rejects_traversal() {
+        let dir = TempDir::new("rhs-tests").unwrap();
+        create_dir(dir.path().join("sub")).unwrap();
+        File::create(dir.path().join("sub").join("a b.txt")).unwrap();
+        File::create(dir.path().join(".env")).unwrap();
+        let mount = Mount::new("/files", dir.path().join("sub").to_str().unwrap());
+
+        let file = dir.path().join("sub").join("a b.txt").canonicalize().unwrap();
+        assert_eq!(Ok(Resolved::File(file)), mount.resolve("/files/./a%20b.txt"));
+        assert_eq!(Err(ResolveError::InvalidPath), mount.resolve("/files/../.env"));
+        assert_eq!(Err(ResolveError::InvalidPath), mount.resolve("/files/%2e%2e/.env"));
+        assert_eq!(Err(ResolveError::InvalidPath), mount.resolve("/files/..%2F.env"));
+        assert_eq!(Err(ResolveError::InvalidPath), mount.resolve("/files/..%5c.env"));
+        assert_eq!(Err(ResolveError::InvalidPath), mount.resolve("/files/a%00.txt"));
+        assert_eq!(Err(ResolveError::NotFound), mount.resolve("/files/missing.txt"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_policy() {
+        use std::os::unix::fs::symlink;
+
+        let dir = TempDir::new("rhs-tests").unwrap();
+        let root = dir.path().join("root");
+        create_dir(&root).unwrap();
+        File::create(root.join("inside.txt")).unwrap();
+        File::create(root.join(".env")).unwrap();
+        File::create(dir.path().join("secret.txt")).unwrap();
+        symlink(root.join("inside.txt"), root.join("link.txt")).unwrap();
+        symlink(root.join(".env"), root.join("cfg")).unwrap();
+        symlink(dir.path().join("secret.txt"), root.join("escape.txt")).unwrap();
+
+        let mut mount = Mount::new("/", root.to_str().unwrap());
+        assert!(mount.resolve("/link.txt").is_ok());
+        assert_eq!(Err(ResolveError::NotFound), mount.resolve("/cfg"));
+        assert_eq!(Err(ResolveError::Forbidden), mount.resolve("/escape.txt"));
+
+        mount.symlinks = SymlinkPolicy::Deny;
+        assert!(mount.resolve("/inside.txt").is_ok());
+        assert_eq!(Err(ResolveError::Forbidden), mount.resolve("/link.txt"));
+
+        mount.symlinks = SymlinkPolicy::Follow;
+        assert!(mount.resolve("/escape.txt").is_ok());
+    }
+}
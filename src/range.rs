@@ -0,0 +1,180 @@
+//! Byte range requests (RFC 7233).
+
+use std::time::SystemTime;
+
+use headers::Headers;
+use utils::date::{parse_http_date, unix_seconds};
+
+/// The most ranges accepted in a single `Range` header. Requests asking for
+/// more are served the whole representation.
+pub const MAX_RANGES: usize = 32;
+
+/// An inclusive range of bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+/// The outcome of evaluating a `Range` header against a representation.
+#[derive(Debug, PartialEq)]
+pub enum Ranges {
+    /// The header was malformed, used an unknown unit or asked for too many
+    /// ranges. The whole representation should be sent.
+    Ignore,
+    /// The ranges to send, sorted with overlapping ranges merged.
+    Satisfiable(Vec<ByteRange>),
+    /// None of the ranges overlapped the representation (416).
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+}
+
+/// Parses a `Range` header value, e.g. `bytes=0-499,-500`, for a
+/// representation of `length` bytes.
+pub fn parse_range(value: &str, length: u64) -> Ranges {
+    let value = value.trim();
+    let specs = match value.find('=') {
+        Some(i) if value[..i].trim().eq_ignore_ascii_case("bytes") => &value[i + 1..],
+        _ => return Ranges::Ignore,
+    };
+
+    let specs: Vec<&str> = specs.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()).collect();
+    if specs.is_empty() || specs.len() > MAX_RANGES {
+        return Ranges::Ignore;
+    }
+
+    let mut ranges = Vec::new();
+    for spec in specs {
+        let dash = match spec.find('-') {
+            Some(dash) => dash,
+            None => return Ranges::Ignore,
+        };
+        let (first, last) = (spec[..dash].trim(), spec[dash + 1..].trim());
+        let range = if first.is_empty() {
+            // A suffix range: the last `n` bytes.
+            let n = match parse_number(last) {
+                Some(n) => n,
+                None => return Ranges::Ignore,
+            };
+            if n == 0 || length == 0 {
+                continue;
+            }
+            ByteRange { start: length.saturating_sub(n), end: length - 1 }
+        } else {
+            let start = match parse_number(first) {
+                Some(start) => start,
+                None => return Ranges::Ignore,
+            };
+            let end = if last.is_empty() {
+                None
+            } else {
+                match parse_number(last) {
+                    Some(end) if end >= start => Some(end),
+                    _ => return Ranges::Ignore,
+                }
+            };
+            if start >= length {
+                continue;
+            }
+            ByteRange { start, end: end.map_or(length - 1, |e| e.min(length - 1)) }
+        };
+        ranges.push(range);
+    }
+
+    if ranges.is_empty() {
+        return Ranges::Unsatisfiable;
+    }
+    Ranges::Satisfiable(coalesce(ranges))
+}
+
+/// Checks if a range request should be honoured given its `If-Range` header.
+/// The ranges are only sent if the validator still matches the current
+/// representation; otherwise the whole representation is sent.
+pub fn if_range_matches(headers: &Headers, etag: Option<&str>, last_modified: Option<SystemTime>) -> bool {
+    let value = match headers.get("if-range") {
+        Some(value) => value.trim(),
+        None => return true,
+    };
+    if value.starts_with('"') || value.starts_with("W/") {
+        // Only strong entity tags can be used (RFC 7233 3.2).
+        !value.starts_with("W/") && etag.map_or(false, |etag| etag == value)
+    } else {
+        match (parse_http_date(value), last_modified) {
+            (Some(date), Some(modified)) => unix_seconds(date) == unix_seconds(modified),
+            _ => false,
+        }
+    }
+}
+
+fn parse_number(value: &str) -> Option<u64> {
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+/// Sorts the ranges and merges any that overlap or are adjacent.
+fn coalesce(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        if let Some(last) = merged.last_mut() {
+            if range.start <= last.end.saturating_add(1) {
+                last.end = last.end.max(range.end);
+                continue;
+            }
+        }
+        merged.push(range);
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: u64, end: u64) -> ByteRange {
+        ByteRange { start, end }
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(Ranges::Satisfiable(vec![range(0, 499)]), parse_range("bytes=0-499", 1000));
+        assert_eq!(Ranges::Satisfiable(vec![range(500, 999)]), parse_range("bytes=500-", 1000));
+        assert_eq!(Ranges::Satisfiable(vec![range(900, 999)]), parse_range("bytes=-100", 1000));
+        assert_eq!(Ranges::Satisfiable(vec![range(0, 999)]), parse_range("bytes=-2000", 1000));
+        assert_eq!(Ranges::Satisfiable(vec![range(990, 999)]), parse_range("bytes=990-5000", 1000));
+        assert_eq!(Ranges::Satisfiable(vec![range(0, 9), range(20, 29)]),
+                   parse_range("bytes=20-29, 0-9", 1000));
+        assert_eq!(Ranges::Satisfiable(vec![range(0, 14)]), parse_range("bytes=0-9,5-14", 1000));
+    }
+
+    #[test]
+    fn test_unsatisfiable_and_ignored_ranges() {
+        assert_eq!(Ranges::Unsatisfiable, parse_range("bytes=1000-", 1000));
+        assert_eq!(Ranges::Unsatisfiable, parse_range("bytes=-0", 1000));
+        assert_eq!(Ranges::Unsatisfiable, parse_range("bytes=0-", 0));
+        assert_eq!(Ranges::Ignore, parse_range("items=0-1", 1000));
+        assert_eq!(Ranges::Ignore, parse_range("bytes=5-1", 1000));
+        assert_eq!(Ranges::Ignore, parse_range("bytes=a-b", 1000));
+        let many = format!("bytes={}", vec!["0-0"; MAX_RANGES + 1].join(","));
+        assert_eq!(Ranges::Ignore, parse_range(&many, 1000));
+    }
+
+    #[test]
+    fn test_if_range() {
+        let modified = parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT");
+        let headers = |value: &str| Headers::parse(vec![format!("If-Range: {}", value).as_str()]).unwrap();
+
+        assert!(if_range_matches(&Headers::new(), None, None));
+        assert!(if_range_matches(&headers("Sun, 06 Nov 1994 08:49:37 GMT"), None, modified));
+        assert!(!if_range_matches(&headers("Sun, 06 Nov 1994 08:49:38 GMT"), None, modified));
+        assert!(if_range_matches(&headers("\"abc\""), Some("\"abc\""), None));
+        assert!(!if_range_matches(&headers("W/\"abc\""), Some("W/\"abc\""), None));
+    }
+}
@@ -0,0 +1,415 @@
+//! Reads HTTP requests from a connection.
+
+use std::io::{self, Read};
+use std::error::Error;
+use std::fmt;
+
+use headers::{Headers, HeaderError, MAX_HEADER_LINE_SIZE};
+use request::{Request, ParseError};
+
+/// The default maximum size of a request body in bytes (10 MiB).
+pub const DEFAULT_MAX_BODY_SIZE: usize = 10 * 1024 * 1024;
+
+/// The maximum size in bytes of the request line and header section combined.
+pub const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+const READ_CHUNK_SIZE: usize = 4096;
+
+/// The maximum size in bytes of a chunk size line including extensions.
+const MAX_CHUNK_LINE_SIZE: usize = 4096;
+
+/// Reads requests from a stream. Bytes read past the end of a request are kept
+/// so they can be used for the next request on the same connection.
+pub struct RequestReader {
+    buf: Vec<u8>,
+    max_body_size: usize,
+}
+
+/// Errors that can occur while reading a request.
+#[derive(Debug)]
+pub enum ReadError {
+    /// The connection was closed before any part of a request was sent.
+    Closed,
+    /// The connection was closed in the middle of a request.
+    UnexpectedEof,
+    Io(io::Error),
+    /// The request line and headers exceeded `MAX_HEAD_SIZE`.
+    HeadTooLarge,
+    /// The request line or headers were malformed.
+    Parse(ParseError),
+    /// The trailer fields of a chunked body were malformed.
+    Header(HeaderError),
+    /// The `Content-Length` header was not a valid length or conflicted with
+    /// another `Content-Length` header.
+    InvalidContentLength,
+    /// The body was larger than the configured maximum body size.
+    PayloadTooLarge,
+    /// Both `Content-Length` and `Transfer-Encoding` were sent. These are
+    /// rejected rather than guessing which one frames the body, since a proxy
+    /// in front of the server may have chosen differently (request smuggling).
+    AmbiguousLength,
+    /// The `Transfer-Encoding` did not end with `chunked` or contained a
+    /// coding other than `chunked`.
+    UnsupportedTransferEncoding,
+    /// A chunk was malformed.
+    InvalidChunk,
+}
+
+impl RequestReader {
+    pub fn new(max_body_size: usize) -> RequestReader {
+        RequestReader {
+            buf: Vec::new(),
+            max_body_size,
+        }
+    }
+
+    /// Reads the next request from the stream. The head is read until the
+    /// empty line that ends the header section then the body is read either
+    /// as exactly `Content-Length` bytes or decoded from chunks when the
+    /// request uses `Transfer-Encoding: chunked`.
+    pub fn read_request<R: Read>(&mut self, stream: &mut R) -> Result<Request, ReadError> {
+        let head_end = self.fill_head(stream)?;
+        let head: Vec<u8> = self.buf.drain(..head_end).collect();
+
+        let mut request = Request::parse(&head)?;
+
+        if request.headers.contains("transfer-encoding") {
+            if request.headers.contains("content-length") {
+                return Err(ReadError::AmbiguousLength);
+            }
+            if !is_chunked(&request.headers) {
+                return Err(ReadError::UnsupportedTransferEncoding);
+            }
+            let (body, trailers) = self.read_chunked_body(stream)?;
+            request.body = body;
+            request.trailers = trailers;
+        } else {
+            let length = content_length(&request.headers)?;
+            if length > self.max_body_size {
+                return Err(ReadError::PayloadTooLarge);
+            }
+            request.body = self.read_body(stream, length)?;
+        }
+
+        Ok(request)
+    }
+
+    /// Reads from the stream until the buffer contains a complete head.
+    /// Returns the length of the head including the terminating empty line.
+    fn fill_head<R: Read>(&mut self, stream: &mut R) -> Result<usize, ReadError> {
+        loop {
+            if let Some(end) = find_head_end(&self.buf) {
+                return Ok(end);
+            }
+            if self.buf.len() > MAX_HEAD_SIZE {
+                return Err(ReadError::HeadTooLarge);
+            }
+            if self.fill(stream)? == 0 {
+                return if self.buf.is_empty() {
+                    Err(ReadError::Closed)
+                } else {
+                    Err(ReadError::UnexpectedEof)
+                };
+            }
+        }
+    }
+
+    /// Takes `length` bytes from the buffer, reading more from the stream if
+    /// needed.
+    fn read_body<R: Read>(&mut self, stream: &mut R, length: usize) -> Result<Vec<u8>, ReadError> {
+        while self.buf.len() < length {
+            if self.fill(stream)? == 0 {
+                return Err(ReadError::UnexpectedEof);
+            }
+        }
+        Ok(self.buf.drain(..length).collect())
+    }
+
+    /// Decodes a chunked body (RFC 7230 4.1). Chunk extensions are ignored.
+    /// Returns the body and the trailer fields sent after the last chunk.
+    fn read_chunked_body<R: Read>(&mut self, stream: &mut R) -> Result<(Vec<u8>, Headers), ReadError> {
+        let mut body = Vec::new();
+        loop {
+            let line = self.read_line(stream, MAX_CHUNK_LINE_SIZE)?;
+            let size = parse_chunk_size(&line)?;
+            if size == 0 {
+                break;
+            }
+            if body.len().checked_add(size).map_or(true, |l| l > self.max_body_size) {
+                return Err(ReadError::PayloadTooLarge);
+            }
+            let data = self.read_body(stream, size)?;
+            body.extend_from_slice(&data);
+            if !self.read_line(stream, 2)?.is_empty() {
+                return Err(ReadError::InvalidChunk);
+            }
+        }
+
+        let mut lines = Vec::new();
+        let mut trailer_size = 0;
+        loop {
+            let line = self.read_line(stream, MAX_HEADER_LINE_SIZE)?;
+            if line.is_empty() {
+                break;
+            }
+            trailer_size += line.len();
+            if trailer_size > MAX_HEAD_SIZE {
+                return Err(ReadError::HeadTooLarge);
+            }
+            lines.push(line);
+        }
+        let trailers = Headers::parse(lines.iter().map(|l| l.as_str()))?;
+
+        Ok((body, trailers))
+    }
+
+    /// Reads a single line, without its line ending, from the buffer reading
+    /// more from the stream if needed. Lines longer than `limit` are treated as
+    /// a malformed chunk.
+    fn read_line<R: Read>(&mut self, stream: &mut R, limit: usize) -> Result<String, ReadError> {
+        loop {
+            if let Some(end) = self.buf.iter().position(|&b| b == b'\n') {
+                let mut line: Vec<u8> = self.buf.drain(..end + 1).collect();
+                line.pop();
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                if line.len() > limit {
+                    return Err(ReadError::InvalidChunk);
+                }
+                return String::from_utf8(line).map_err(|_| ReadError::InvalidChunk);
+            }
+            if self.buf.len() > limit + 2 {
+                return Err(ReadError::InvalidChunk);
+            }
+            if self.fill(stream)? == 0 {
+                return Err(ReadError::UnexpectedEof);
+            }
+        }
+    }
+
+    /// Reads a single chunk from the stream into the buffer.
+    fn fill<R: Read>(&mut self, stream: &mut R) -> Result<usize, ReadError> {
+        let mut chunk = [0u8; READ_CHUNK_SIZE];
+        let n = stream.read(&mut chunk)?;
+        self.buf.extend_from_slice(&chunk[..n]);
+        Ok(n)
+    }
+}
+
+/// Finds the end of the head (the empty line ending the header section).
+/// Bare `\n` line endings are accepted as well as `\r\n`.
+fn find_head_end(buf: &[u8]) -> Option<usize> {
+    for (i, &b) in buf.iter().enumerate() {
+        if b != b'\n' {
+            continue;
+        }
+        let rest = &buf[i + 1..];
+        if rest.starts_with(b"\n") {
+            return Some(i + 2);
+        }
+        if rest.starts_with(b"\r\n") {
+            return Some(i + 3);
+        }
+    }
+    None
+}
+
+/// Checks if `chunked` is the final and only transfer coding applied to the
+/// body. Other codings are not supported.
+fn is_chunked(headers: &Headers) -> bool {
+    let codings: Vec<String> = headers.get_all("transfer-encoding").iter()
+        .flat_map(|v| v.split(','))
+        .map(|c| c.trim().to_lowercase())
+        .filter(|c| !c.is_empty())
+        .collect();
+    codings == ["chunked"]
+}
+
+/// Parses a chunk size line: the size in hex optionally followed by
+/// `;name=value` extensions.
+fn parse_chunk_size(line: &str) -> Result<usize, ReadError> {
+    let size = line.split(';').next().unwrap_or("").trim_end_matches(|c| c == ' ' || c == '\t');
+    if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(ReadError::InvalidChunk);
+    }
+    usize::from_str_radix(size, 16).map_err(|_| ReadError::InvalidChunk)
+}
+
+/// Returns the body length declared by the `Content-Length` header, or 0 if
+/// there is none. Repeated headers must all agree (RFC 7230 3.3.2).
+fn content_length(headers: &Headers) -> Result<usize, ReadError> {
+    let mut length = None;
+    for value in headers.get_all("content-length") {
+        for v in value.split(',') {
+            let v = v.trim();
+            if v.is_empty() || !v.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(ReadError::InvalidContentLength);
+            }
+            let v = v.parse::<usize>().map_err(|_| ReadError::InvalidContentLength)?;
+            if length.map_or(false, |l| l != v) {
+                return Err(ReadError::InvalidContentLength);
+            }
+            length = Some(v);
+        }
+    }
+    Ok(length.unwrap_or(0))
+}
+
+impl From<io::Error> for ReadError {
+    fn from(e: io::Error) -> Self {
+        ReadError::Io(e)
+    }
+}
+
+impl From<ParseError> for ReadError {
+    fn from(e: ParseError) -> Self {
+        ReadError::Parse(e)
+    }
+}
+
+impl From<HeaderError> for ReadError {
+    fn from(e: HeaderError) -> Self {
+        ReadError::Header(e)
+    }
+}
+
+impl ReadError {
+    fn message(&self) -> &str {
+        match *self {
+            ReadError::Closed => "Connection closed by client",
+            ReadError::UnexpectedEof => "Connection closed in the middle of a request",
+            ReadError::Io(_) => "Error reading from connection",
+            ReadError::HeadTooLarge => "Request head too large",
+            ReadError::Parse(_) => "Malformed request",
+            ReadError::Header(_) => "Malformed trailer fields",
+            ReadError::InvalidContentLength => "Invalid Content-Length",
+            ReadError::PayloadTooLarge => "Request body too large",
+            ReadError::AmbiguousLength => "Both Content-Length and Transfer-Encoding were sent",
+            ReadError::UnsupportedTransferEncoding => "Unsupported Transfer-Encoding",
+            ReadError::InvalidChunk => "Malformed chunk in request body",
+        }
+    }
+}
+
+impl Error for ReadError {
+    fn description(&self) -> &str {
+        self.message()
+    }
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReadError::Io(ref e) => write!(f, "{}: {}", self.message(), e),
+            ReadError::Parse(ref e) => write!(f, "{}: {}", self.message(), e),
+            ReadError::Header(ref e) => write!(f, "{}: {}", self.message(), e),
+            _ => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// A stream that returns one byte per read.
+    struct Trickle(Cursor<Vec<u8>>);
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(1);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn test_read_body_with_content_length() {
+        let data = b"POST /submit HTTP/1.1\r\nHost: localhost\r\nContent-Length: 11\r\n\r\nhello world".to_vec();
+        let mut stream = Trickle(Cursor::new(data));
+        let mut reader = RequestReader::new(DEFAULT_MAX_BODY_SIZE);
+
+        let request = reader.read_request(&mut stream).unwrap();
+
+        assert_eq!("/submit", request.path);
+        assert_eq!(b"hello world".to_vec(), request.body);
+    }
+
+    #[test]
+    fn test_bytes_after_request_are_kept() {
+        let data = b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 3\r\n\r\nabcGET /next HTTP/1.1\r\nHost: localhost\r\n\r\n".to_vec();
+        let mut stream = Cursor::new(data);
+        let mut reader = RequestReader::new(DEFAULT_MAX_BODY_SIZE);
+
+        let first = reader.read_request(&mut stream).unwrap();
+        let second = reader.read_request(&mut stream).unwrap();
+
+        assert_eq!(b"abc".to_vec(), first.body);
+        assert_eq!("/next", second.path);
+        assert!(second.body.is_empty());
+        match reader.read_request(&mut stream) {
+            Err(ReadError::Closed) => {},
+            _ => panic!("Expected the connection to be closed"),
+        }
+    }
+
+    #[test]
+    fn test_body_size_limits() {
+        let data = b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 11\r\n\r\nhello world".to_vec();
+        let mut reader = RequestReader::new(10);
+        match reader.read_request(&mut Cursor::new(data)) {
+            Err(ReadError::PayloadTooLarge) => {},
+            _ => panic!("Expected the body to be rejected"),
+        }
+
+        let data = b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\nab".to_vec();
+        let mut reader = RequestReader::new(DEFAULT_MAX_BODY_SIZE);
+        match reader.read_request(&mut Cursor::new(data)) {
+            Err(ReadError::InvalidContentLength) => {},
+            _ => panic!("Expected conflicting lengths to be rejected"),
+        }
+    }
+
+    #[test]
+    fn test_read_chunked_body() {
+        let data = b"POST /upload HTTP/1.1\r\n\
+                     Host: localhost\r\n\
+                     Transfer-Encoding: chunked\r\n\r\n\
+                     5;name=value\r\nhello\r\n\
+                     6\r\n world\r\n\
+                     0\r\n\
+                     Checksum: abc\r\n\r\n\
+                     GET /next HTTP/1.1\r\nHost: localhost\r\n\r\n".to_vec();
+        let mut stream = Trickle(Cursor::new(data));
+        let mut reader = RequestReader::new(DEFAULT_MAX_BODY_SIZE);
+
+        let request = reader.read_request(&mut stream).unwrap();
+        assert_eq!(b"hello world".to_vec(), request.body);
+        assert_eq!(Some("abc"), request.trailers.get("checksum"));
+
+        let next = reader.read_request(&mut stream).unwrap();
+        assert_eq!("/next", next.path);
+    }
+
+    #[test]
+    fn test_ambiguous_framing_is_rejected() {
+        let data = b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n".to_vec();
+        match RequestReader::new(DEFAULT_MAX_BODY_SIZE).read_request(&mut Cursor::new(data)) {
+            Err(ReadError::AmbiguousLength) => {},
+            _ => panic!("Expected Content-Length with Transfer-Encoding to be rejected"),
+        }
+
+        let data = b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked, gzip\r\n\r\n0\r\n\r\n".to_vec();
+        match RequestReader::new(DEFAULT_MAX_BODY_SIZE).read_request(&mut Cursor::new(data)) {
+            Err(ReadError::UnsupportedTransferEncoding) => {},
+            _ => panic!("Expected chunked not being the final coding to be rejected"),
+        }
+
+        let data = b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n\r\n".to_vec();
+        match RequestReader::new(DEFAULT_MAX_BODY_SIZE).read_request(&mut Cursor::new(data)) {
+            Err(ReadError::InvalidChunk) => {},
+            _ => panic!("Expected an invalid chunk size to be rejected"),
+        }
+    }
+}
@@ -1,13 +1,32 @@
 use std::string::String;
+use std::error::Error;
 use std::fmt::{Display, Formatter, Result};
 
+use headers::{Headers, HeaderError};
+use status::StatusCode;
+use utils::url::{percent_decode, form_decode};
+
 pub struct Request {
+    /// The percent decoded path of the request target, without the query.
     pub path: String, 
+    /// The path of the request target as sent, before percent decoding.
+    pub raw_path: String,
+    /// The raw query string of the request target, without the `?`.
+    pub query_string: String,
+    pub query: Query,
+    /// The parameters captured by the route pattern of the handler serving
+    /// the request, e.g. `id` for `/users/:id`.
+    pub params: Params,
     pub kind: RequestKind,
-    pub data: String,
+    pub version: String,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+    /// Trailer fields sent after a chunked body.
+    pub trailers: Headers,
     pub host: String,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RequestKind {
     Get,
     Post,
@@ -17,6 +36,47 @@ pub enum RequestKind {
     Options,
 }
 
+/// The maximum length in bytes of a request target.
+pub const MAX_URI_LENGTH: usize = 8192;
+
+/// The methods accepted by the server, sent in the `Allow` header of a 405
+/// response.
+pub const ALLOWED_METHODS: &str = "GET, HEAD, POST, PUT, DELETE, OPTIONS";
+
+/// Errors that can occur while parsing the head of a request.
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    /// The request line was not `method SP target SP version`.
+    BadRequestLine,
+    /// The method is a standard method the server does not allow (e.g.
+    /// `TRACE`).
+    MethodNotAllowed(String),
+    /// The method is not known to the server.
+    UnknownMethod(String),
+    /// The version was well formed but not HTTP/1.0 or HTTP/1.1.
+    UnsupportedVersion(String),
+    /// The request line or headers were not valid utf8.
+    InvalidUtf8,
+    /// An HTTP/1.1 request did not send a `Host` header.
+    MissingHost,
+    /// The request target was longer than `MAX_URI_LENGTH`.
+    UriTooLong,
+    Header(HeaderError),
+}
+
+/// The parameters of a query string in the order they were sent. A key may
+/// appear more than once.
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    pairs: Vec<(String, String)>,
+}
+
+/// The parameters captured from the path by a route pattern.
+#[derive(Debug, Clone, Default)]
+pub struct Params {
+    pairs: Vec<(String, String)>,
+}
+
 impl RequestKind {
     fn from(string: &str) -> Option<RequestKind> { 
         match &*string { 
@@ -32,24 +92,255 @@ impl RequestKind {
 }
 
 impl Request {
-    pub fn from(request_data: String) -> Request {
-        let (request_type, path, _http_version, host_name) = parse(&request_data);
-        Request {
-            path,
-            data: request_data,
-            kind: RequestKind::from(&request_type).unwrap(),
-            host: host_name,
+    /// Parses a request from its head (the request line and headers). The
+    /// body is left empty.
+    pub fn parse(head: &[u8]) -> ::std::result::Result<Request, ParseError> {
+        let head = ::std::str::from_utf8(head).map_err(|_| ParseError::InvalidUtf8)?;
+        let (kind, target, version, headers) = parse_head(head)?;
+        let (path, query_string) = split_target(&target);
+        if version == "HTTP/1.1" && !headers.contains("host") {
+            return Err(ParseError::MissingHost);
+        }
+        let host = headers.get("host").unwrap_or("").to_string();
+        Ok(Request {
+            path: percent_decode(path),
+            raw_path: path.to_string(),
+            query: Query::parse(query_string),
+            query_string: query_string.to_string(),
+            params: Params::default(),
+            body: Vec::new(),
+            trailers: Headers::new(),
+            kind,
+            version,
+            headers,
+            host,
+        })
+    }
+
+    /// Returns the first value of the header `name`. Header names are case
+    /// insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    /// Returns every value sent for the header `name`.
+    pub fn headers_all(&self, name: &str) -> Vec<&str> {
+        self.headers.get_all(name)
+    }
+
+    /// Returns the first value of the query parameter `key`.
+    pub fn query(&self, key: &str) -> Option<&str> {
+        self.query.get(key)
+    }
+
+    /// Returns every value of the query parameter `key`.
+    pub fn query_all(&self, key: &str) -> Vec<&str> {
+        self.query.get_all(key)
+    }
+
+    /// Returns the route parameter `name`.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name)
+    }
+
+    /// Checks if the client wants the connection to stay open after this
+    /// request. HTTP/1.1 connections are persistent unless the client sends
+    /// `Connection: close`; HTTP/1.0 connections are only persistent if the
+    /// client sends `Connection: keep-alive`.
+    pub fn keep_alive(&self) -> bool {
+        let has_option = |option: &str| {
+            self.headers_all("connection").iter()
+                .flat_map(|v| v.split(','))
+                .any(|o| o.trim().eq_ignore_ascii_case(option))
+        };
+        if self.version == "HTTP/1.1" {
+            !has_option("close")
+        } else {
+            has_option("keep-alive")
+        }
+    }
+}
+
+impl Query {
+    /// Parses an `application/x-www-form-urlencoded` query string. Keys and
+    /// values are percent decoded and a key without `=` has an empty value.
+    pub fn parse(query_string: &str) -> Query {
+        let pairs = query_string.split('&')
+            .filter(|p| !p.is_empty())
+            .map(|p| {
+                let mut split = p.splitn(2, '=');
+                let key = split.next().unwrap_or("");
+                let value = split.next().unwrap_or("");
+                (form_decode(key), form_decode(value))
+            })
+            .collect();
+        Query {
+            pairs,
+        }
+    }
+
+    /// Returns the first value of `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.pairs.iter()
+            .find(|&&(ref k, _)| k == key)
+            .map(|&(_, ref v)| v.as_str())
+    }
+
+    /// Returns every value of `key` in the order they were sent.
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.pairs.iter()
+            .filter(|&&(ref k, _)| k == key)
+            .map(|&(_, ref v)| v.as_str())
+            .collect()
+    }
+}
+
+impl Params {
+    /// Creates the parameters from `(name, value)` pairs.
+    pub fn from(pairs: Vec<(String, String)>) -> Params {
+        Params {
+            pairs,
+        }
+    }
+
+    /// Returns the value of the parameter `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.pairs.iter()
+            .find(|&&(ref n, _)| n == name)
+            .map(|&(_, ref v)| v.as_str())
+    }
+
+    /// Parses the value of the parameter `name`, e.g. `params.parse::<u64>("id")`.
+    pub fn parse<T: ::std::str::FromStr>(&self, name: &str) -> Option<T> {
+        self.get(name).and_then(|v| v.parse().ok())
+    }
+
+    /// Iterates over `(name, value)` pairs in the order they appear in the
+    /// route pattern.
+    pub fn iter(&self) -> ::std::slice::Iter<(String, String)> {
+        self.pairs.iter()
+    }
+}
+
+/// Splits a request target into its path and query string. The scheme and
+/// authority of an absolute URI (sent to proxies) are removed, as is any
+/// fragment.
+fn split_target(target: &str) -> (&str, &str) {
+    let mut target = target.split('#').next().unwrap_or("");
+    if let Some(rest) = strip_scheme(target) {
+        target = rest.find(|c| c == '/' || c == '?').map_or("/", |i| &rest[i..]);
+    }
+    match target.find('?') {
+        Some(i) => (&target[..i], &target[i + 1..]),
+        None => (target, ""),
+    }
+}
+
+/// Returns the rest of an absolute-form target after its `http://` or
+/// `https://` scheme.
+fn strip_scheme(target: &str) -> Option<&str> {
+    ["http://", "https://"].iter()
+        .find(|scheme| {
+            target.len() >= scheme.len() && target.as_bytes()[..scheme.len()].eq_ignore_ascii_case(scheme.as_bytes())
+        })
+        .map(|scheme| &target[scheme.len()..])
+}
+
+/// Parses the head of a request.
+/// Returns (HTTP Method, Route, HTTP Version, Headers)
+fn parse_head(head: &str) -> ::std::result::Result<(RequestKind, String, String, Headers), ParseError> {
+    let mut lines = head.split('\n');
+    let request_line = lines.next().unwrap_or("").trim_end_matches('\r');
+    let parts: Vec<&str> = request_line.split(' ').collect();
+    if parts.len() != 3 || parts.iter().any(|p| p.is_empty()) {
+        return Err(ParseError::BadRequestLine);
+    }
+    let (method, target, version) = (parts[0], parts[1], parts[2]);
+
+    let kind = match RequestKind::from(method) {
+        Some(kind) => kind,
+        None => return Err(match method {
+            "TRACE" | "CONNECT" | "PATCH" => ParseError::MethodNotAllowed(method.to_string()),
+            _ if method.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_') => {
+                ParseError::UnknownMethod(method.to_string())
+            }
+            _ => ParseError::BadRequestLine,
+        }),
+    };
+
+    if target.len() > MAX_URI_LENGTH {
+        return Err(ParseError::UriTooLong);
+    }
+    if !target.starts_with('/') && strip_scheme(target).is_none() && target != "*" {
+        return Err(ParseError::BadRequestLine);
+    }
+
+    match version {
+        "HTTP/1.0" | "HTTP/1.1" => {}
+        _ if is_http_version(version) => return Err(ParseError::UnsupportedVersion(version.to_string())),
+        _ => return Err(ParseError::BadRequestLine),
+    }
+
+    let headers = Headers::parse(lines)?;
+    Ok((kind, target.to_string(), version.to_string(), headers))
+}
+
+/// Checks if `version` has the form `HTTP/x.y`.
+fn is_http_version(version: &str) -> bool {
+    let bytes = version.as_bytes();
+    bytes.len() == 8 && version.starts_with("HTTP/") && bytes[6] == b'.'
+        && bytes[5].is_ascii_digit() && bytes[7].is_ascii_digit()
+}
+
+impl ParseError {
+    /// Returns the status of the response sent for this error.
+    pub fn status(&self) -> StatusCode {
+        match *self {
+            ParseError::MethodNotAllowed(_) => StatusCode::METHOD_NOT_ALLOWED,
+            ParseError::UnknownMethod(_) => StatusCode::NOT_IMPLEMENTED,
+            ParseError::UnsupportedVersion(_) => StatusCode::HTTP_VERSION_NOT_SUPPORTED,
+            ParseError::UriTooLong => StatusCode::URI_TOO_LONG,
+            ParseError::Header(HeaderError::TooManyFields) |
+            ParseError::Header(HeaderError::LineTooLong) => StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn message(&self) -> &str {
+        match *self {
+            ParseError::BadRequestLine => "Malformed request line",
+            ParseError::MethodNotAllowed(_) => "Method not allowed",
+            ParseError::UnknownMethod(_) => "Unknown method",
+            ParseError::UnsupportedVersion(_) => "Unsupported HTTP version",
+            ParseError::InvalidUtf8 => "Request head is not valid utf8",
+            ParseError::MissingHost => "HTTP/1.1 request without a Host header",
+            ParseError::UriTooLong => "Request target too long",
+            ParseError::Header(_) => "Malformed request headers",
         }
     }
 }
 
-/// Parses the request as a string
-/// Returns (HTTP Method, Route, HTTP Version)
-fn parse(request_string: &str) -> (String, String, String, String) {
-    let mut line_split = request_string.split('\n');
-    let first_line: Vec<&str> = line_split.nth(0).unwrap().split(' ').collect();
-    let second_line: Vec<&str> = line_split.nth(1).unwrap().split(' ').collect();
-    (first_line[0].to_string(), first_line[1].to_string(), first_line[2].to_string(), second_line[1].to_string())
+impl From<HeaderError> for ParseError {
+    fn from(e: HeaderError) -> Self {
+        ParseError::Header(e)
+    }
+}
+
+impl Error for ParseError {
+    fn description(&self) -> &str {
+        self.message()
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match *self {
+            ParseError::MethodNotAllowed(ref m) | ParseError::UnknownMethod(ref m) |
+            ParseError::UnsupportedVersion(ref m) => write!(f, "{}: {:?}", self.message(), m),
+            ParseError::Header(ref e) => write!(f, "{}: {}", self.message(), e),
+            _ => write!(f, "{}", self.message()),
+        }
+    }
 }
 
 impl Display for Request {
@@ -69,3 +360,92 @@ impl Display for RequestKind {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_headers() {
+        let data = "GET /index.html HTTP/1.1\r\n\
+                    User-Agent: test\r\n\
+                    Host: localhost:8080\r\n\
+                    Cookie: a=1\r\n\
+                    Cookie: b=2\r\n\r\n";
+        let request = Request::parse(data.as_bytes()).unwrap();
+
+        assert_eq!("/index.html", request.path);
+        assert_eq!("HTTP/1.1", request.version);
+        assert_eq!("localhost:8080", request.host);
+        assert_eq!(Some("test"), request.header("user-agent"));
+        assert_eq!(vec!["a=1", "b=2"], request.headers_all("COOKIE"));
+    }
+
+    #[test]
+    fn test_request_without_host() {
+        let request = Request::parse(b"GET / HTTP/1.0\r\n\r\n").unwrap();
+
+        assert_eq!("", request.host);
+        assert!(request.headers.is_empty());
+    }
+
+    #[test]
+    fn test_query_string() {
+        let request = Request::parse(b"GET /static/app%20v2.js?v=3&tag=a&tag=b+c&flag HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+
+        assert_eq!("/static/app v2.js", request.path);
+        assert_eq!("/static/app%20v2.js", request.raw_path);
+        assert_eq!("v=3&tag=a&tag=b+c&flag", request.query_string);
+        assert_eq!(Some("3"), request.query("v"));
+        assert_eq!(vec!["a", "b c"], request.query_all("tag"));
+        assert_eq!(Some(""), request.query("flag"));
+        assert_eq!(None, request.query("missing"));
+    }
+
+    #[test]
+    fn test_absolute_request_target() {
+        let request = Request::parse(b"GET http://example.com/index.html?a=1 HTTP/1.1\r\nHost: example.com\r\n\r\n").unwrap();
+
+        assert_eq!("/index.html", request.path);
+        assert_eq!("a=1", request.query_string);
+
+        let request = Request::parse(b"GET HTTPS://example.com HTTP/1.1\r\nHost: example.com\r\n\r\n").unwrap();
+        assert_eq!("/", request.path);
+    }
+
+    #[test]
+    fn test_keep_alive() {
+        let request = |head: &str| Request::parse(head.as_bytes()).unwrap();
+
+        assert!(request("GET / HTTP/1.1\r\nHost: a\r\n\r\n").keep_alive());
+        assert!(!request("GET / HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n").keep_alive());
+        assert!(!request("GET / HTTP/1.0\r\n\r\n").keep_alive());
+        assert!(request("GET / HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n").keep_alive());
+    }
+
+    #[test]
+    fn test_malformed_requests_are_rejected() {
+        let error = |head: &[u8]| Request::parse(head).err().unwrap();
+
+        assert_eq!(ParseError::BadRequestLine, error(b"GET /\r\n\r\n"));
+        assert_eq!(ParseError::BadRequestLine, error(b"GET  / HTTP/1.1\r\n\r\n"));
+        assert_eq!(ParseError::BadRequestLine, error(b"GET / FTP/1.0\r\n\r\n"));
+        assert_eq!(ParseError::BadRequestLine, error(b"GET httpfoo HTTP/1.0\r\n\r\n"));
+        assert_eq!(ParseError::BadRequestLine, error(b"GET https-nope/x HTTP/1.0\r\n\r\n"));
+        assert_eq!(ParseError::InvalidUtf8, error(b"GET /\xff HTTP/1.0\r\n\r\n"));
+        assert_eq!(ParseError::MissingHost, error(b"GET / HTTP/1.1\r\n\r\n"));
+
+        let long = format!("GET /{} HTTP/1.0\r\n\r\n", "a".repeat(MAX_URI_LENGTH));
+        assert_eq!(StatusCode::URI_TOO_LONG, error(long.as_bytes()).status());
+    }
+
+    #[test]
+    fn test_parse_error_status() {
+        let status = |head: &[u8]| Request::parse(head).err().unwrap().status();
+
+        assert_eq!(StatusCode::METHOD_NOT_ALLOWED, status(b"TRACE / HTTP/1.0\r\n\r\n"));
+        assert_eq!(StatusCode::NOT_IMPLEMENTED, status(b"BREW /pot HTTP/1.0\r\n\r\n"));
+        assert_eq!(StatusCode::HTTP_VERSION_NOT_SUPPORTED, status(b"GET / HTTP/2.0\r\n\r\n"));
+        assert_eq!(StatusCode::BAD_REQUEST, status(b"GET / HTTP/1.0\r\nBad Name: x\r\n\r\n"));
+    }
+}
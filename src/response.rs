@@ -1,61 +1,94 @@
 extern crate chrono;
 
 use std::string::String;
-use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use self::chrono::Local;
 
+use range::ByteRange;
+use status::StatusCode;
+
+static BOUNDARY_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// The size of the chunks a file body is written in.
+pub const FILE_CHUNK_SIZE: usize = 64 * 1024;
+
 /// HTTP Response
 pub struct Response {
-    kind: ResponseType,
+    status: StatusCode,
     headers: Vec<String>,
-    body: Option<String>,
-    file: Option<Vec<u8>>,
+    body: Body,
+}
+
+/// The body of a response.
+pub enum Body {
+    Empty,
+    Text(String),
+    Bytes(Vec<u8>),
+    /// A file streamed from its current position. Contains the number of bytes
+    /// to send so the file is never read into memory as a whole.
+    File(File, u64),
+    /// Pieces of a file interleaved with bytes, e.g. a `multipart/byteranges`
+    /// body.
+    Segments(File, Vec<Segment>),
 }
 
-/// The HTTP response status represented as an enum.
-pub enum ResponseType {
-    NotFound,
-    HTTPOk,
-    #[allow(dead_code)]
-    Redirection,
-    ServerError,
+/// A piece of a `Body::Segments` body.
+pub enum Segment {
+    Bytes(Vec<u8>),
+    /// A range of the file.
+    File(ByteRange),
 }
 
 impl Response {
 
+    /// Creates a response with the status and a text body. Headers such as
+    /// `Content-Type` can be added with `add_response_headers`.
+    pub fn new(status: StatusCode, body: String) -> Response {
+        let mut response = Response::new_text(body, None, status);
+        response.default_headers();
+        response
+    }
+
     /// Creates a new response where the body is text based.
-    fn new_text(body: String, headers: Option<Vec<String>>, kind: ResponseType) -> Response {
-        Response {
-            body: Some(body),
-            headers: headers.unwrap_or_default(),
-            kind,
-            file: None,
-        }
+    fn new_text(body: String, headers: Option<Vec<String>>, status: StatusCode) -> Response {
+        Response::with_body(Body::Text(body), headers, status)
     }
 
     /// Creates a new response where the body is a buffer of bytes.
-    pub fn new_file(file: Vec<u8>, headers: Option<Vec<String>>, kind: ResponseType)-> Response {
+    pub fn new_bytes(bytes: Vec<u8>, headers: Option<Vec<String>>, status: StatusCode) -> Response {
+        Response::with_body(Body::Bytes(bytes), headers, status)
+    }
+
+    /// Creates a new response streaming `length` bytes of the file from its
+    /// current position.
+    pub fn new_file(file: File, length: u64, headers: Option<Vec<String>>, status: StatusCode) -> Response {
+        Response::with_body(Body::File(file, length), headers, status)
+    }
+
+    fn with_body(body: Body, headers: Option<Vec<String>>, status: StatusCode) -> Response {
         Response {
-            body: None,
+            body,
             headers: headers.unwrap_or_default(),
-            kind,
-            file: Some(file),
+            status,
         }
     }
 
-    #[allow(dead_code)]
     /// An HTTP 200 response with a text body.
     pub fn http_ok(body: String) -> Response {
-        let mut response = Response::new_text(body, None, ResponseType::HTTPOk);
+        let mut response = Response::new_text(body, None, StatusCode::OK);
         response.default_headers();
         response
     }
 
-    /// An HTTP 200 response with a file body.
-    pub fn http_ok_file(file: Vec<u8>) -> Response {
-        let mut response = Response::new_file(file, None, ResponseType::HTTPOk); 
+    /// An HTTP 200 response streaming `length` bytes of the file.
+    pub fn http_ok_file(file: File, length: u64, content_type: &str) -> Response {
+        let mut response = Response::new_file(file, length, None, StatusCode::OK);
         response.default_headers();
+        response.add_response_headers(&format!("Content-Type: {}", content_type));
         response
     }
 
@@ -111,48 +144,251 @@ impl Response {
 </body>
 </html>
 <!-- IE needs 512+ bytes: https://blogs.msdn.microsoft.com/ieinternals/2010/08/18/friendly-http-error-pages/ -->"#.to_string();
-        let mut response = Response::new_text(body, None, ResponseType::NotFound);
+        let mut response = Response::new_text(body, None, StatusCode::NOT_FOUND);
         response.default_headers();
         response
     }
 
+    /// An HTTP 206 response with a single range of the file. `length` is the
+    /// length of the whole file.
+    pub fn partial_content(mut file: File, range: ByteRange, length: u64, content_type: &str) -> io::Result<Response> {
+        file.seek(SeekFrom::Start(range.start))?;
+        let mut response = Response::new_file(file, range.len(), None, StatusCode::PARTIAL_CONTENT);
+        response.default_headers();
+        response.add_response_headers(&format!("Content-Type: {}", content_type));
+        response.add_response_headers(&format!("Content-Range: bytes {}-{}/{}", range.start, range.end, length));
+        Ok(response)
+    }
+
+    /// An HTTP 206 response with several ranges of the file sent as a
+    /// `multipart/byteranges` body. `length` is the length of the whole file.
+    pub fn partial_content_multipart(file: File, ranges: &[ByteRange], length: u64, content_type: &str) -> Response {
+        let boundary = multipart_boundary();
+        let mut segments = Vec::new();
+        for (i, range) in ranges.iter().enumerate() {
+            let part_headers = format!("{}--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                                       if i == 0 { "" } else { "\r\n" },
+                                       boundary, content_type, range.start, range.end, length);
+            segments.push(Segment::Bytes(part_headers.into_bytes()));
+            segments.push(Segment::File(*range));
+        }
+        segments.push(Segment::Bytes(format!("\r\n--{}--\r\n", boundary).into_bytes()));
+
+        let mut response = Response::with_body(Body::Segments(file, segments), None, StatusCode::PARTIAL_CONTENT);
+        response.default_headers();
+        response.add_response_headers(&format!("Content-Type: multipart/byteranges; boundary={}", boundary));
+        response
+    }
+
+    /// An HTTP 416 response. `length` is the length of the whole
+    /// representation.
+    pub fn range_not_satisfiable(length: u64) -> Response {
+        let mut response = Response::error(StatusCode::RANGE_NOT_SATISFIABLE);
+        response.add_response_headers(&format!("Content-Range: bytes */{}", length));
+        response
+    }
+
+    /// An HTTP 301 response redirecting to `location`.
+    pub fn moved_permanently(location: &str) -> Response {
+        Response::redirect(StatusCode::MOVED_PERMANENTLY, location)
+    }
+
+    /// An HTTP 302 response redirecting to `location`.
+    pub fn found(location: &str) -> Response {
+        Response::redirect(StatusCode::FOUND, location)
+    }
+
+    /// An HTTP 308 response redirecting to `location`. Unlike 301 the method
+    /// and body of the request are kept.
+    pub fn permanent_redirect(location: &str) -> Response {
+        Response::redirect(StatusCode::PERMANENT_REDIRECT, location)
+    }
+
+    /// A redirection response with a `Location` header.
+    fn redirect(status: StatusCode, location: &str) -> Response {
+        let mut response = Response::error(status);
+        response.add_response_headers(&format!("Location: {}", location));
+        response
+    }
+
+    /// An HTTP 304 response. It never has a body.
+    pub fn not_modified() -> Response {
+        let mut response = Response::with_body(Body::Empty, None, StatusCode::NOT_MODIFIED);
+        response.default_headers();
+        response
+    }
+
+    /// An HTTP 400 response. Sent when the request could not be parsed.
+    pub fn bad_request() -> Response {
+        Response::error(StatusCode::BAD_REQUEST)
+    }
+
+    /// An HTTP 401 response. `challenge` is sent in the `WWW-Authenticate`
+    /// header, e.g. `Basic realm="admin"`.
+    pub fn unauthorized(challenge: &str) -> Response {
+        let mut response = Response::error(StatusCode::UNAUTHORIZED);
+        response.add_response_headers(&format!("WWW-Authenticate: {}", challenge));
+        response
+    }
+
+    /// An HTTP 403 response.
+    pub fn forbidden() -> Response {
+        Response::error(StatusCode::FORBIDDEN)
+    }
+
+    /// An HTTP 405 response. `allow` lists the methods the resource supports,
+    /// e.g. `GET, HEAD`.
+    pub fn method_not_allowed(allow: &str) -> Response {
+        let mut response = Response::error(StatusCode::METHOD_NOT_ALLOWED);
+        response.add_response_headers(&format!("Allow: {}", allow));
+        response
+    }
+
+    /// An HTTP 200 response to an `OPTIONS` request. `allow` lists the methods
+    /// the resource supports.
+    pub fn options(allow: &str) -> Response {
+        let mut response = Response::new(StatusCode::OK, String::new());
+        response.add_response_headers(&format!("Allow: {}", allow));
+        response
+    }
+
+    /// An HTTP 412 response. Sent when a precondition such as `If-Match`
+    /// fails.
+    pub fn precondition_failed() -> Response {
+        Response::error(StatusCode::PRECONDITION_FAILED)
+    }
+
+    /// An HTTP 413 response. Sent when the request body exceeds the configured
+    /// maximum body size.
+    pub fn payload_too_large() -> Response {
+        Response::error(StatusCode::PAYLOAD_TOO_LARGE)
+    }
+
+    /// An HTTP 414 response. Sent when the request target is too long.
+    pub fn uri_too_long() -> Response {
+        Response::error(StatusCode::URI_TOO_LONG)
+    }
+
+    /// An HTTP 431 response. Sent when the header section is too large.
+    pub fn request_header_fields_too_large() -> Response {
+        Response::error(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
+    }
+
     /// An HTTP 500 response. The body is provided.
     pub fn server_error() -> Response {
-        let mut r = Response {
-            body: Some(String::from("<html><body><h1>rust-http-server: Internal server error</h1></body></html>")),
-            headers: Vec::new(),
-            kind: ResponseType::ServerError,
-            file: None,
-        };
-        r.default_headers();
-        r
+        Response::error(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    /// An HTTP 503 response.
+    pub fn service_unavailable() -> Response {
+        Response::error(StatusCode::SERVICE_UNAVAILABLE)
+    }
+
+    /// A response for `status` with a short html body naming the status.
+    pub fn error(status: StatusCode) -> Response {
+        let body = format!("<html><body><h1>rust-http-server: {}</h1></body></html>", status.reason());
+        let mut response = Response::new_text(body, None, status);
+        response.default_headers();
+        response.add_response_headers("Content-Type: text/html; charset=utf-8");
+        response
     }
 
     /// Creates the default headers for every response.
-    /// Status line, Date, Server name
+    /// Status line, Date, Server name, Content-Length (unless the status
+    /// forbids a body)
     fn default_headers(&mut self) {
-        let status = format!("{} {} {}", self.http_version(), self.code(), self.kind);
+        let status = format!("{} {}", self.http_version(), self.status);
         let date = format!("Date: {}", Local::now().to_rfc2822());
         let server = format!("Server: {}", self.server());
+        let length = format!("Content-Length: {}", self.content_length());
         self.add_response_headers(&status);
         self.add_response_headers(&date);
         self.add_response_headers(&server);
+        if self.status.allows_body() {
+            self.add_response_headers(&length);
+        }
+    }
+
+    /// Adds the `Connection` header telling the client if the connection will
+    /// stay open after this response.
+    pub fn set_keep_alive(&mut self, keep_alive: bool) {
+        let connection = if keep_alive { "keep-alive" } else { "close" };
+        self.add_response_headers(&format!("Connection: {}", connection));
+    }
+
+    /// Returns the length of the body in bytes.
+    pub fn content_length(&self) -> u64 {
+        match self.body {
+            Body::Empty => 0,
+            Body::Text(ref text) => text.len() as u64,
+            Body::Bytes(ref bytes) => bytes.len() as u64,
+            Body::File(_, length) => length,
+            Body::Segments(_, ref segments) => segments.iter().map(|s| match *s {
+                Segment::Bytes(ref bytes) => bytes.len() as u64,
+                Segment::File(range) => range.len(),
+            }).sum(),
+        }
+    }
+
+    pub fn body(&self) -> &Body {
+        &self.body
+    }
+
+    /// Removes the body, e.g. for a response to a `HEAD` request. Headers
+    /// describing the body such as `Content-Length` are kept.
+    pub fn remove_body(&mut self) {
+        self.body = Body::Empty;
+    }
+
+    /// Replaces the body. `Content-Length` is updated if it was set.
+    pub fn set_body(&mut self, body: Body) {
+        self.body = body;
+        if self.header("Content-Length").is_some() {
+            let length = self.content_length().to_string();
+            self.set_header("Content-Length", &length);
+        }
     }
 
     pub fn add_response_headers(&mut self, header: &str) {
         self.headers.push(header.to_string());
     }
 
-    /// Returns HTTP status code for ResponseType
-    pub fn code(&self) -> u16 {
-        match self.kind {
-            ResponseType::HTTPOk => { 200 },
-            ResponseType::Redirection => { 300 },
-            ResponseType::NotFound => { 404 },
-            ResponseType::ServerError => { 500 },
+    /// Returns the value of the first header `name`. Header names are case
+    /// insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .filter_map(|h| split_header(h))
+            .find(|&(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v)
+    }
+
+    /// Replaces every header `name` with a single header.
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        self.remove_header(name);
+        self.add_response_headers(&format!("{}: {}", name, value));
+    }
+
+    /// Removes every header `name`.
+    pub fn remove_header(&mut self, name: &str) {
+        self.headers.retain(|h| split_header(h).map_or(true, |(n, _)| !n.eq_ignore_ascii_case(name)));
+    }
+
+    /// Turns a strong `ETag` into a weak one, for bodies that are equivalent
+    /// to but not the same bytes as the ones it was created for.
+    pub fn weaken_etag(&mut self) {
+        let weak_etag = self.header("ETag")
+            .filter(|etag| !etag.starts_with("W/"))
+            .map(|etag| format!("W/{}", etag));
+        if let Some(etag) = weak_etag {
+            self.set_header("ETag", &etag);
         }
     }
 
+    /// Returns the HTTP status of the response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
     /// Returns the HTTP version. (Currently 1.1)
     pub fn http_version(&self) -> &str {
         "HTTP/1.1"
@@ -162,53 +398,111 @@ impl Response {
         String::from(env!("CARGO_PKG_NAME"))
     }
 
+    /// Returns the response as a `String`. File bodies are not included.
     pub fn to_string(&self) -> String {
-        let mut headers = self.headers_only();
-        let body_copy = self.body.clone();
-        headers.push_str(&body_copy.unwrap());
-        headers
+        let mut result = self.headers_only();
+        match self.body {
+            Body::Text(ref text) => result.push_str(text),
+            Body::Bytes(ref bytes) => result.push_str(&String::from_utf8_lossy(bytes)),
+            Body::Empty | Body::File(..) | Body::Segments(..) => {}
+        }
+        result
     }
 
     /// Returns a `String` of the response containing only the headers. The body can be immediately
     /// append to this.
-    fn headers_only(&self) -> String {
+    pub fn headers_only(&self) -> String {
         let mut result = self.headers.join("\r\n").to_string();
         result.push_str("\r\n\r\n");
         result
     }
 
-    /// Returns a byte representation of the response
+    /// Returns a byte representation of the response. File bodies are read
+    /// into memory so this should only be used for small responses.
     pub fn to_bytes(&self) -> Vec<u8> {
         vprintln!("Converting response to binary");
-        let string_rep = self.headers_only();
-        let mut result = string_rep.into_bytes();
-        let mut body = self.file.clone().unwrap();
-        result.append(&mut body);
+        let mut result = Vec::new();
+        if let Err(e) = self.write_to(&mut result) {
+            error!("Error reading response body: {:?}", e);
+        }
         result
     }
 
-    #[allow(dead_code)]
-    fn mime(&self) -> &str {
-        // TODO: Implement mime type header
-        unimplemented!();
+    /// Writes the response. File bodies are written in chunks of
+    /// `FILE_CHUNK_SIZE` bytes.
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(self.headers_only().as_bytes())?;
+        match self.body {
+            Body::Empty => Ok(()),
+            Body::Text(ref text) => out.write_all(text.as_bytes()),
+            Body::Bytes(ref bytes) => out.write_all(bytes),
+            Body::File(ref file, length) => copy_file(file, out, length),
+            Body::Segments(ref file, ref segments) => {
+                for segment in segments {
+                    match *segment {
+                        Segment::Bytes(ref bytes) => out.write_all(bytes)?,
+                        Segment::File(range) => {
+                            let mut file = file;
+                            file.seek(SeekFrom::Start(range.start))?;
+                            copy_file(file, out, range.len())?;
+                        }
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+}
+
+/// Splits a header line into its name and value. The status line has no name.
+fn split_header(line: &str) -> Option<(&str, &str)> {
+    let colon = line.find(':')?;
+    let name = &line[..colon];
+    if name.contains(' ') {
+        return None;
     }
+    Some((name, line[colon + 1..].trim()))
+}
 
+/// Creates a boundary for a multipart body. It only needs to be unlikely to
+/// appear in the parts.
+fn multipart_boundary() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let count = BOUNDARY_COUNT.fetch_add(1, Ordering::Relaxed);
+    format!("{:x}{:08x}{:x}", now.as_secs(), now.subsec_nanos(), count)
 }
 
-impl fmt::Display for ResponseType {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            ResponseType::HTTPOk => write!(f, "{}", "OK"),
-            ResponseType::Redirection => write!(f, "{}", "Redirection"),
-            ResponseType::NotFound => write!(f, "{}", "Not Found"),
-            ResponseType::ServerError => write!(f, "{}", "Internal Server Error"),
+/// Copies `length` bytes from the current position of the file in chunks of
+/// `FILE_CHUNK_SIZE` bytes.
+pub fn copy_file<W: Write>(mut file: &File, out: &mut W, length: u64) -> io::Result<()> {
+    let mut buf = vec![0u8; FILE_CHUNK_SIZE];
+    let mut remaining = length;
+    while remaining > 0 {
+        let len = remaining.min(FILE_CHUNK_SIZE as u64) as usize;
+        let n = file.read(&mut buf[..len])?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "File shorter than its length"));
         }
+        out.write_all(&buf[..n])?;
+        remaining -= n as u64;
     }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    extern crate tempdir;
+
     use super::*;
+    use self::tempdir::TempDir;
+
+    /// Creates a file with the contents and opens it for reading.
+    fn test_file(dir: &TempDir, contents: &[u8]) -> File {
+        let path = dir.path().join("test.css");
+        File::create(&path).unwrap().write_all(contents).unwrap();
+        File::open(&path).unwrap()
+    }
     
     /// Ensures the response is a valid HTTP response
     #[test]
@@ -216,6 +510,7 @@ mod tests {
         let status_line = "HTTP/1.1 200 OK";
         let server_header = format!("Server: {}", env!("CARGO_PKG_NAME"));
         let body = "This is the body";
+        let length_header = format!("Content-Length: {}", body.len());
 
         let test_response = Response::http_ok(body.to_string());
 
@@ -223,12 +518,68 @@ mod tests {
         
         let return_newline = result.matches("\r\n").count();
 
-        assert_eq!(4, return_newline);
+        assert_eq!(5, return_newline);
 
         let split_response: Vec<&str> = result.split("\r\n").collect();
 
         assert_eq!(status_line, split_response[0]);
         assert_eq!(server_header, split_response[2]);
+        assert_eq!(length_header, split_response[3]);
         assert_eq!(body, split_response[split_response.len() - 1]);
     }
+
+    #[test]
+    fn test_file_response_headers() {
+        let dir = TempDir::new("rhs-tests").unwrap();
+        let response = Response::http_ok_file(test_file(&dir, b"body {}"), 7, "text/css; charset=utf-8");
+
+        let result = String::from_utf8(response.to_bytes()).unwrap();
+
+        assert!(result.contains("\r\nContent-Length: 7\r\n"));
+        assert!(result.contains("\r\nContent-Type: text/css; charset=utf-8\r\n"));
+        assert!(result.ends_with("\r\n\r\nbody {}"));
+    }
+
+    #[test]
+    fn test_file_body_is_written_in_chunks() {
+        let dir = TempDir::new("rhs-tests").unwrap();
+        let contents: Vec<u8> = (0..FILE_CHUNK_SIZE * 2 + 10).map(|i| i as u8).collect();
+        let file = test_file(&dir, &contents);
+
+        let mut out = Vec::new();
+        copy_file(&file, &mut out, contents.len() as u64).unwrap();
+        assert_eq!(contents, out);
+
+        let file = test_file(&dir, b"short");
+        assert!(copy_file(&file, &mut Vec::new(), 10).is_err());
+    }
+
+    #[test]
+    fn test_connection_header() {
+        let mut response = Response::not_found();
+        response.set_keep_alive(false);
+
+        let result = String::from_utf8(response.to_bytes()).unwrap();
+
+        assert!(result.contains("\r\nConnection: close\r\n\r\n"));
+    }
+
+    #[test]
+    fn test_redirect_response() {
+        let result = Response::moved_permanently("/new/").to_string();
+
+        assert!(result.starts_with("HTTP/1.1 301 Moved Permanently\r\n"));
+        assert!(result.contains("\r\nLocation: /new/\r\n"));
+    }
+
+    #[test]
+    fn test_not_modified_has_no_body() {
+        let response = Response::not_modified();
+        let result = response.to_string();
+
+        assert_eq!(StatusCode::NOT_MODIFIED, response.status());
+        assert!(result.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+        assert!(!result.contains("Content-Length"));
+        assert!(result.ends_with("\r\n\r\n"));
+    }
 }
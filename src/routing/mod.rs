@@ -0,0 +1,829 @@
+//! The servers router module.
+extern crate walkdir;
+
+mod tree;
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf, Component};
+use std::ffi::OsStr;
+use std::sync::{Arc, Mutex, RwLock};
+
+use self::walkdir::WalkDir;
+
+pub use self::tree::{RouteTree, RouteError};
+
+use cache::CachePolicy;
+use compression::{Compression, Encoding};
+use handler::Handler;
+use livereload::LiveReload;
+use mime::MimeTypes;
+use mount::{Mount, Resolved, ResolveError};
+use request::{Params, RequestKind, ALLOWED_METHODS};
+use utils::file::is_hidden;
+use utils::url::{percent_decode, percent_encode_path};
+
+/// Stores routes in a hashmap. Checks if the request is trying to access a static
+/// resouce and provides file location for the resource. Only files in the specified
+/// folder(s) are visible on the server. Static routes are generated on server
+/// startup. With `watch` enabled in the config they are kept up to date with
+/// `refresh_static_path` as files change, otherwise the server must be
+/// restarted for changes to take effect.
+#[derive(Clone)]
+pub struct Router {
+    pub static_routes: HashMap<String, String>,
+    /// The folders registered with `register_static_routes`.
+    pub static_folders: Vec<String>,
+    /// The directories containing the static files by URL, without a trailing
+    /// slash.
+    pub static_dirs: HashMap<String, String>,
+    /// Directories whose files are found when requested rather than having
+    /// routes.
+    pub mounts: Vec<Mount>,
+    /// Whether directories are served as listing pages.
+    pub autoindex: bool,
+    /// The files served for a directory, in order of preference.
+    pub index_files: Vec<String>,
+    /// How paths matching a route only with their trailing slash added or
+    /// removed are handled.
+    pub trailing_slash: TrailingSlash,
+    /// The file served for page requests matching no route, for single page
+    /// applications with client side routing.
+    pub spa_fallback: Option<String>,
+    /// Reloads pages in the browser when static files change, in development
+    /// mode.
+    pub live_reload: Option<LiveReload>,
+    /// Precompressed versions of static files by the path of the file, e.g.
+    /// `app.js.br` for `app.js`.
+    pub precompressed: HashMap<String, Vec<(Encoding, String)>>,
+    /// Rust handlers registered by route pattern.
+    pub handlers: RouteTree<MethodRoutes>,
+    /// Content types used for the static files.
+    pub mime_types: MimeTypes,
+    /// Caching headers sent with the static files.
+    pub cache_policy: CachePolicy,
+    /// Compression applied to responses.
+    pub compression: Compression,
+}
+
+/// The handlers registered for a route pattern by method.
+#[derive(Clone, Default)]
+pub struct MethodRoutes {
+    methods: Vec<(RequestKind, Arc<Handler>)>,
+    /// A handler for every method.
+    any: Option<Arc<Handler>>,
+}
+
+/// The result of routing a request.
+pub enum RouteMatch<'a> {
+    /// A static file. Contains the path to the file.
+    Static(Cow<'a, str>),
+    /// A static directory to list. Contains the path to the directory.
+    Directory(Cow<'a, str>),
+    /// A handler and the parameters captured from the path.
+    Handler(&'a Arc<Handler>, Params),
+    /// An `OPTIONS` request for a route without an `OPTIONS` handler. Contains
+    /// the methods allowed for the route.
+    Options(String),
+    /// The route exists but not for the method. Contains the methods allowed
+    /// for the route.
+    MethodNotAllowed(String),
+    /// The resource is at another path. Contains the path.
+    Redirect(String),
+    /// The path could escape a mount's directory.
+    BadRequest,
+    /// The path leads through a symbolic link a mount doesn't follow.
+    Forbidden,
+    NotFound,
+}
+
+/// How paths that only match a route with their trailing slash added or
+/// removed are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrailingSlash {
+    /// The path must match exactly.
+    Strict,
+    /// The client is redirected to the path of the route.
+    Redirect,
+    /// The route is served at both paths.
+    Merge,
+}
+
+/// The index files served for a directory when none are configured.
+pub const DEFAULT_INDEX_FILES: [&str; 2] = ["index.html", "index.htm"];
+
+/// The methods allowed for static files.
+const STATIC_METHODS: &str = "GET, HEAD, OPTIONS";
+
+/// Restricts a static file or directory match to the static methods.
+fn static_match(method: RequestKind, found: RouteMatch) -> RouteMatch {
+    match method {
+        RequestKind::Get | RequestKind::Head => found,
+        RequestKind::Options => RouteMatch::Options(STATIC_METHODS.to_string()),
+        _ => RouteMatch::MethodNotAllowed(STATIC_METHODS.to_string()),
+    }
+}
+
+impl<'a> RouteMatch<'a> {
+    fn is_not_found(&self) -> bool {
+        match *self {
+            RouteMatch::NotFound => true,
+            _ => false,
+        }
+    }
+}
+
+impl Router {
+    /// Initialize a `Router` without any routes.
+    pub fn new() -> Router {
+        let static_routes = HashMap::new();
+
+        Router {
+            static_routes,
+            precompressed: HashMap::new(),
+            static_folders: Vec::new(),
+            static_dirs: HashMap::new(),
+            mounts: Vec::new(),
+            autoindex: false,
+            index_files: DEFAULT_INDEX_FILES.iter().map(|f| f.to_string()).collect(),
+            trailing_slash: TrailingSlash::Redirect,
+            spa_fallback: None,
+            live_reload: None,
+            handlers: RouteTree::new(),
+            mime_types: MimeTypes::new(),
+            cache_policy: CachePolicy::default(),
+            compression: Compression::default(),
+        }
+    }
+
+    /// Initialize a `Router` and immediately register routes for the directory provided.
+    pub fn from(folder: &str) -> Router {
+        let mut router = Router::new();
+        router.register_static_routes(folder);
+        router
+    }
+
+    /// Creates routes for files within a directory. Files ending in `.br` or
+    /// `.gz` next to a file with the same name are not given their own route,
+    /// they are served in place of that file to clients accepting the encoding.
+    pub fn register_static_routes(&mut self, folder: &str) {
+        vprintln!("Registering routes for: {}", &folder);
+        let directory = Path::new(&folder);
+        let folder_name = directory.file_name().map_or("", |f| f.to_str().unwrap());
+
+        self.register_path(folder_name, directory);
+        self.group_precompressed();
+        if !self.static_folders.iter().any(|f| f == folder) {
+            self.static_folders.push(folder.to_string());
+        }
+        vprintln!("Routes: {:?}", self.static_routes);
+    }
+
+    /// Updates the static routes for a file or directory in one of the
+    /// registered folders after it was created, changed or removed.
+    pub fn refresh_static_path(&mut self, path: &Path) {
+        let folder = match self.static_folders.iter().find(|f| path.starts_with(f)) {
+            Some(folder) => Path::new(folder).to_path_buf(),
+            None => return,
+        };
+        let folder_name = folder.file_name().map_or("", |f| f.to_str().unwrap()).to_string();
+        let hidden = path.strip_prefix(&folder).map(|relative| {
+            relative.components().any(|c| c.as_os_str().to_str().map_or(false, |c| c.starts_with('.')))
+        }).unwrap_or(true);
+        if hidden {
+            return;
+        }
+
+        self.remove_path(&folder_name, path);
+        if path.exists() {
+            self.register_path(&folder_name, path);
+        }
+        self.group_precompressed();
+    }
+
+    /// Adds routes for the file or the files and directories within the
+    /// directory at the path.
+    fn register_path(&mut self, folder: &str, path: &Path) {
+        for entry in WalkDir::new(path).into_iter()
+            .filter_entry(|e| !is_hidden(e) ) {
+            let entry = match entry {
+                Ok(e) => e,
+                _ => continue,
+            };
+            let metadata = entry.metadata().ok();
+            if metadata.as_ref().map_or(false, |m| m.is_file()) {
+                let url = Router::create_url(entry.path(), folder);
+                let abs_path = entry.path();
+                self.static_routes.insert(url, 
+                                          abs_path.to_str().unwrap().to_string());
+            } else if metadata.as_ref().map_or(false, |m| m.is_dir()) {
+                let url = Router::create_url(entry.path(), folder);
+                self.static_dirs.insert(url, entry.path().to_str().unwrap().to_string());
+            }
+        }
+    }
+
+    /// Removes the routes for the file or everything within the directory at
+    /// the path. Precompressed versions of removed files get their own routes.
+    fn remove_path(&mut self, folder: &str, path: &Path) {
+        let under = |file: &str| Path::new(file).starts_with(path);
+        self.static_routes.retain(|_, file| !under(file));
+        self.static_dirs.retain(|_, dir| !under(dir));
+
+        let removed: Vec<String> = self.precompressed.keys().filter(|f| under(f)).cloned().collect();
+        for file in removed {
+            for (_, variant) in self.precompressed.remove(&file).unwrap_or_default() {
+                if !under(&variant) {
+                    let url = Router::create_url(Path::new(&variant), folder);
+                    self.static_routes.insert(url, variant);
+                }
+            }
+        }
+        for files in self.precompressed.values_mut() {
+            files.retain(|&(_, ref variant)| !under(variant));
+        }
+        self.precompressed.retain(|_, files| !files.is_empty());
+    }
+
+    /// Moves static routes for precompressed files to the routes of the
+    /// files they were created from.
+    fn group_precompressed(&mut self) {
+        let variants: Vec<(String, String, Encoding)> = self.static_routes.keys()
+            .filter_map(|url| {
+                let dot = url.rfind('.')?;
+                let encoding = Encoding::from_extension(&url[dot + 1..])?;
+                let original = &url[..dot];
+                if self.static_routes.contains_key(original) {
+                    Some((url.clone(), original.to_string(), encoding))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        for (url, original, encoding) in variants {
+            if let Some(path) = self.static_routes.remove(&url) {
+                let original = self.static_routes[&original].clone();
+                let files = self.precompressed.entry(original).or_insert_with(Vec::new);
+                files.retain(|&(e, _)| e != encoding);
+                files.push((encoding, path));
+            }
+        }
+    }
+
+    /// Returns the precompressed versions of the static file at the path.
+    pub fn precompressed(&self, file: &str) -> &[(Encoding, String)] {
+        self.precompressed.get(file).map_or(&[], |files| files.as_slice())
+    }
+
+    /// Registers a handler for requests matching `pattern` with the method, or
+    /// every method if `method` is `None`. A handler already registered for
+    /// the same pattern and method is replaced. See `RouteTree` for the
+    /// pattern syntax.
+    pub fn register_handler(&mut self,
+                            pattern: &str,
+                            method: Option<RequestKind>,
+                            handler: Arc<Handler>) -> Result<(), RouteError> {
+        let routes = self.handlers.get_or_insert_with(pattern, MethodRoutes::default)?;
+        match method {
+            Some(method) => {
+                routes.methods.retain(|&(m, _)| m != method);
+                routes.methods.push((method, handler));
+            }
+            None => routes.any = Some(handler),
+        }
+        Ok(())
+    }
+
+    /// Finds what should serve a request. Static files and directories are
+    /// only served for `GET` and `HEAD`, then handlers are matched by the most
+    /// specific pattern. `OPTIONS` requests are answered with the allowed
+    /// methods unless a handler is registered for them. A path that only
+    /// matches with its trailing slash added or removed is handled according
+    /// to `trailing_slash`.
+    ///
+    /// Takes the path as sent by the client so an encoded `/` in a handler
+    /// parameter doesn't split it into several segments.
+    pub fn route(&self, method: RequestKind, raw_path: &str) -> RouteMatch {
+        if raw_path == "*" && method == RequestKind::Options {
+            return RouteMatch::Options(ALLOWED_METHODS.to_string());
+        }
+
+        let found = self.find(method, raw_path);
+        if !found.is_not_found() || raw_path == "/" || self.trailing_slash == TrailingSlash::Strict {
+            return found;
+        }
+
+        let alternate = if raw_path.ends_with('/') {
+            raw_path.trim_end_matches('/').to_string()
+        } else {
+            format!("{}/", raw_path)
+        };
+        match self.find(method, &alternate) {
+            RouteMatch::NotFound => RouteMatch::NotFound,
+            _ if self.trailing_slash == TrailingSlash::Redirect => RouteMatch::Redirect(percent_decode(&alternate)),
+            found => found,
+        }
+    }
+
+    /// Returns the SPA fallback file for a request that matched no route. Only
+    /// `GET` and `HEAD` requests accepting HTML whose last path segment has no
+    /// extension fall back so missing assets still get a 404. With an
+    /// application the server only falls back if the application answers 404.
+    pub fn fallback(&self, method: RequestKind, path: &str, accept: Option<&str>) -> Option<&str> {
+        let file = self.spa_fallback.as_ref()?;
+        let accepts_html = accept.map_or(false, accepts_html);
+        let has_extension = path.rsplit('/').next().map_or(false, |segment| segment.contains('.'));
+        match method {
+            RequestKind::Get | RequestKind::Head if accepts_html && !has_extension => Some(file),
+            _ => None,
+        }
+    }
+
+    /// Finds what serves exactly the path. A directory is served by its first
+    /// index file or its listing if `autoindex` is set. Requests for a
+    /// directory without the trailing slash are redirected so relative links
+    /// in the page work.
+    fn find(&self, method: RequestKind, raw_path: &str) -> RouteMatch {
+        let path = percent_decode(raw_path);
+        let path = path.as_str();
+        if let Some(file) = self.static_routes.get(path) {
+            return static_match(method, RouteMatch::Static(Cow::Borrowed(file)));
+        }
+
+        let dir_url = path.trim_end_matches('/');
+        if let Some(dir) = self.static_dirs.get(dir_url) {
+            let index = self.index_files.iter()
+                .filter_map(|name| self.static_routes.get(&format!("{}/{}", dir_url, name)))
+                .next();
+            let found = match index {
+                Some(file) => RouteMatch::Static(Cow::Borrowed(file)),
+                None if self.autoindex => RouteMatch::Directory(Cow::Borrowed(dir)),
+                None => RouteMatch::NotFound,
+            };
+            return match found {
+                RouteMatch::NotFound => RouteMatch::NotFound,
+                _ if !path.ends_with('/') => RouteMatch::Redirect(format!("{}/", path)),
+                found => static_match(method, found),
+            };
+        }
+
+        match self.handlers.find(&decode_segments(raw_path)) {
+            Some((routes, params)) => match routes.get(method) {
+                Some(handler) => {
+                    let params = params.into_iter().map(|(name, value)| (name, percent_decode(&value))).collect();
+                    RouteMatch::Handler(handler, Params::from(params))
+                }
+                None if method == RequestKind::Options => RouteMatch::Options(routes.allow()),
+                None => RouteMatch::MethodNotAllowed(routes.allow()),
+            },
+            None => RouteMatch::NotFound,
+        }
+    }
+
+    /// Finds the file or directory for a path in the mounts, resolving it
+    /// against the file system. Takes the path as sent by the client, see
+    /// `Mount::resolve`. Directories and trailing slashes are handled like
+    /// they are for static routes. The mount with the longest URL is used if
+    /// several contain the path.
+    pub fn route_mounted(&self, method: RequestKind, raw_path: &str) -> RouteMatch {
+        let mount = match self.mounts.iter().filter(|m| m.relative(raw_path).is_some()).max_by_key(|m| m.url.len()) {
+            Some(mount) => mount,
+            None => return RouteMatch::NotFound,
+        };
+        let to_string = |path: PathBuf| Cow::Owned(path.to_string_lossy().into_owned());
+
+        match mount.resolve(raw_path) {
+            Ok(Resolved::File(file)) => {
+                if raw_path.ends_with('/') {
+                    match self.trailing_slash {
+                        TrailingSlash::Strict => return RouteMatch::NotFound,
+                        TrailingSlash::Redirect => {
+                            return RouteMatch::Redirect(percent_decode(raw_path.trim_end_matches('/')));
+                        }
+                        TrailingSlash::Merge => {}
+                    }
+                }
+                static_match(method, RouteMatch::Static(to_string(file)))
+            }
+            Ok(Resolved::Directory(dir)) => {
+                let dir_path = raw_path.trim_end_matches('/');
+                let index = self.index_files.iter()
+                    .filter_map(|name| match mount.resolve(&format!("{}/{}", dir_path, percent_encode_path(name))) {
+                        Ok(Resolved::File(file)) => Some(file),
+                        _ => None,
+                    })
+                    .next();
+                let found = match index {
+                    Some(file) => RouteMatch::Static(to_string(file)),
+                    None if self.autoindex => RouteMatch::Directory(to_string(dir)),
+                    None => return RouteMatch::NotFound,
+                };
+                if raw_path.ends_with('/') {
+                    static_match(method, found)
+                } else {
+                    RouteMatch::Redirect(format!("{}/", percent_decode(raw_path)))
+                }
+            }
+            Err(ResolveError::InvalidPath) => RouteMatch::BadRequest,
+            Err(ResolveError::Forbidden) => RouteMatch::Forbidden,
+            Err(ResolveError::NotFound) => RouteMatch::NotFound,
+        }
+    }
+
+    /// Creates a URL for the resource.
+    /// Example:
+    /// A user is working in directory `app`.
+    /// The user registers the directory `static` within `app`
+    /// The URLs for the files with in static will be `/static/foo.js` etc.
+    fn create_url(path: &Path, dir: &str) -> String {
+        let components = path.components();
+
+        let filtered: Vec<&str> = components
+            .skip_while(|c| Some(*c) != Some(Component::Normal(OsStr::new(dir))))
+            .map(|c| c.as_os_str())
+            .filter_map(|c| c.to_str())
+            .collect();
+        let result = filtered.join("/");
+        if result.starts_with('/') {
+            result
+        } else {
+            format!("/{}", result)
+        }
+    }
+
+    /// Checks if the parent of the directory URL is also a static directory,
+    /// or the directory is below the URL of a mount.
+    pub fn has_parent_dir(&self, url: &str) -> bool {
+        let url = url.trim_end_matches('/');
+        url.rfind('/').map_or(false, |i| self.static_dirs.contains_key(&url[..i]))
+            || self.mounts.iter().any(|m| m.relative(url).map_or(false, |rest| !rest.is_empty()))
+    }
+
+    /// Checks if the route provided is an actual resource.
+    #[cfg(test)]
+    pub fn is_static_content(&self, path: &str) -> bool {
+        self.static_routes.contains_key(path)
+    }
+}
+
+/// A router shared by the connections of a running server. Changes are made
+/// to a copy which then replaces the router, so requests are never blocked
+/// while routes are updated and each request sees a consistent router.
+#[derive(Clone)]
+pub struct SharedRouter {
+    current: Arc<RwLock<Arc<Router>>>,
+    /// Serializes updates so none are lost.
+    update: Arc<Mutex<()>>,
+}
+
+impl SharedRouter {
+    pub fn new(router: Router) -> SharedRouter {
+        SharedRouter {
+            current: Arc::new(RwLock::new(Arc::new(router))),
+            update: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Returns the current router.
+    pub fn load(&self) -> Arc<Router> {
+        match self.current.read() {
+            Ok(router) => Arc::clone(&router),
+            Err(poisoned) => Arc::clone(&poisoned.into_inner()),
+        }
+    }
+
+    /// Applies the change to a copy of the current router and replaces it.
+    pub fn update<F: FnOnce(&mut Router)>(&self, change: F) {
+        let _guard = self.update.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut router = (*self.load()).clone();
+        change(&mut router);
+        let router = Arc::new(router);
+        match self.current.write() {
+            Ok(mut current) => *current = router,
+            Err(poisoned) => *poisoned.into_inner() = router,
+        }
+    }
+}
+
+impl MethodRoutes {
+    /// Returns the handler for the method. `HEAD` requests are served by the
+    /// `GET` handler when there is no `HEAD` handler.
+    fn get(&self, method: RequestKind) -> Option<&Arc<Handler>> {
+        let find = |method| self.methods.iter().find(|&&(m, _)| m == method).map(|&(_, ref h)| h);
+        find(method)
+            .or_else(|| if method == RequestKind::Head { find(RequestKind::Get) } else { None })
+            .or_else(|| self.any.as_ref())
+    }
+
+    /// Returns the value of the `Allow` header for the route.
+    fn allow(&self) -> String {
+        if self.any.is_some() {
+            return ALLOWED_METHODS.to_string();
+        }
+        let methods = [RequestKind::Get, RequestKind::Head, RequestKind::Post,
+                       RequestKind::Put, RequestKind::Delete, RequestKind::Options];
+        methods.iter()
+            .filter(|&&m| self.get(m).is_some() || m == RequestKind::Options)
+            .map(|m| m.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+/// Percent decodes each segment of a raw path on its own. A `/` or `%` within
+/// a segment stays encoded so the segments can be told apart after decoding.
+fn decode_segments(raw_path: &str) -> String {
+    raw_path.split('/')
+        .map(|segment| percent_decode(segment).replace('%', "%25").replace('/', "%2F"))
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+/// Checks if an `Accept` header lists `text/html` with a q-value above 0.
+fn accepts_html(accept: &str) -> bool {
+    accept.split(',').any(|item| {
+        let mut parts = item.split(';');
+        let range = parts.next().unwrap_or("").trim();
+        let q = parts
+            .filter_map(|p| {
+                let p = p.trim();
+                if p.len() > 2 && p[..2].eq_ignore_ascii_case("q=") {
+                    p[2..].parse::<f32>().ok()
+                } else {
+                    None
+                }
+            })
+            .next()
+            .unwrap_or(1.0);
+        range.eq_ignore_ascii_case("text/html") && q > 0.0
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+    use std::fs::File;
+    use self::tempdir::TempDir;
+
+    #[test]
+    fn test_register_static_routes() {
+        // Check if the size of the hash table is 1 after adding a file
+        let test_dir = TempDir::new("rhs-tests").unwrap();
+        let filepath = test_dir.path().join("test.txt");
+        let _ = File::create(filepath).unwrap();
+
+        let mut router = Router::new();
+        assert_eq!(0, router.static_routes.len());
+
+        router.register_static_routes(test_dir.path().to_str().unwrap());
+
+        assert_eq!(1, router.static_routes.len());
+    }
+
+    #[test]
+    fn test_precompressed_files_are_grouped() {
+        let test_dir = TempDir::new("rhs-tests").unwrap();
+        let dir_name = test_dir.path().file_name().unwrap().to_str().unwrap().to_string();
+        for name in &["app.js", "app.js.br", "app.js.gz", "archive.tar.gz"] {
+            File::create(test_dir.path().join(name)).unwrap();
+        }
+
+        let router = Router::from(test_dir.path().to_str().unwrap());
+
+        let url = format!("/{}/app.js", dir_name);
+        assert!(router.is_static_content(&url));
+        assert!(!router.is_static_content(&format!("{}.br", url)));
+        assert!(router.is_static_content(&format!("/{}/archive.tar.gz", dir_name)));
+        let mut encodings: Vec<&str> = router.precompressed(&router.static_routes[&url])
+            .iter().map(|&(e, _)| e.name()).collect();
+        encodings.sort();
+        assert_eq!(vec!["br", "gzip"], encodings);
+        let archive = &router.static_routes[&format!("/{}/archive.tar.gz", dir_name)];
+        assert!(router.precompressed(archive).is_empty());
+    }
+
+    #[test]
+    fn test_directory_routes() {
+        let test_dir = TempDir::new("rhs-tests").unwrap();
+        let dir_name = test_dir.path().file_name().unwrap().to_str().unwrap().to_string();
+        ::std::fs::create_dir(test_dir.path().join("sub")).unwrap();
+
+        let mut router = Router::from(test_dir.path().to_str().unwrap());
+        let url = format!("/{}/sub", dir_name);
+        assert!(match router.route(RequestKind::Get, &url) { RouteMatch::NotFound => true, _ => false });
+
+        router.autoindex = true;
+        assert!(match router.route(RequestKind::Get, &format!("{}/", url)) { RouteMatch::Directory(_) => true, _ => false });
+        assert!(router.has_parent_dir(&url));
+        assert!(!router.has_parent_dir(&format!("/{}/", dir_name)));
+    }
+
+    #[test]
+    fn test_index_files_and_trailing_slashes() {
+        let test_dir = TempDir::new("rhs-tests").unwrap();
+        let dir_name = test_dir.path().file_name().unwrap().to_str().unwrap().to_string();
+        ::std::fs::create_dir(test_dir.path().join("docs")).unwrap();
+        File::create(test_dir.path().join("docs").join("index.htm")).unwrap();
+        File::create(test_dir.path().join("page.html")).unwrap();
+
+        let mut router = Router::from(test_dir.path().to_str().unwrap());
+        let is_redirect = |found: RouteMatch, to: &str| match found {
+            RouteMatch::Redirect(location) => location == to,
+            _ => false,
+        };
+        let is_static = |found: RouteMatch| match found { RouteMatch::Static(_) => true, _ => false };
+
+        let docs = format!("/{}/docs", dir_name);
+        assert!(is_redirect(router.route(RequestKind::Get, &docs), &format!("{}/", docs)));
+        assert!(is_static(router.route(RequestKind::Get, &format!("{}/", docs))));
+        let page = format!("/{}/page.html", dir_name);
+        assert!(is_redirect(router.route(RequestKind::Get, &format!("{}/", page)), &page));
+
+        router.trailing_slash = TrailingSlash::Merge;
+        assert!(is_static(router.route(RequestKind::Get, &format!("{}/", page))));
+        router.trailing_slash = TrailingSlash::Strict;
+        assert!(router.route(RequestKind::Get, &format!("{}/", page)).is_not_found());
+        assert!(is_redirect(router.route(RequestKind::Get, &docs), &format!("{}/", docs)));
+
+        router.index_files = vec!["index.html".to_string()];
+        assert!(router.route(RequestKind::Get, &format!("{}/", docs)).is_not_found());
+    }
+
+    #[test]
+    fn test_route_mounted() {
+        let test_dir = TempDir::new("rhs-tests").unwrap();
+        ::std::fs::create_dir(test_dir.path().join("docs")).unwrap();
+        File::create(test_dir.path().join("docs").join("index.html")).unwrap();
+        File::create(test_dir.path().join("app.js")).unwrap();
+
+        let mut router = Router::new();
+        router.mounts.push(Mount::new("/assets", test_dir.path().to_str().unwrap()));
+        let index = test_dir.path().join("docs").join("index.html").canonicalize().unwrap();
+        let is_file = |found: RouteMatch, file: &Path| match found {
+            RouteMatch::Static(path) => Path::new(path.as_ref()) == file,
+            _ => false,
+        };
+        let is_redirect = |found: RouteMatch, to: &str| match found {
+            RouteMatch::Redirect(location) => location == to,
+            _ => false,
+        };
+
+        assert!(is_file(router.route_mounted(RequestKind::Get, "/assets/docs/"), &index));
+        assert!(is_redirect(router.route_mounted(RequestKind::Get, "/assets/docs"), "/assets/docs/"));
+        assert!(is_redirect(router.route_mounted(RequestKind::Get, "/assets/app.js/"), "/assets/app.js"));
+        assert!(router.route_mounted(RequestKind::Get, "/assets/new.js").is_not_found());
+        File::create(test_dir.path().join("new.js")).unwrap();
+        assert!(is_file(router.route_mounted(RequestKind::Head, "/assets/new.js"),
+                        &test_dir.path().join("new.js").canonicalize().unwrap()));
+
+        assert!(match router.route_mounted(RequestKind::Get, "/assets/docs/..%2Fapp.js") {
+            RouteMatch::BadRequest => true,
+            _ => false,
+        });
+        assert!(match router.route_mounted(RequestKind::Post, "/assets/app.js") {
+            RouteMatch::MethodNotAllowed(_) => true,
+            _ => false,
+        });
+        assert!(router.route_mounted(RequestKind::Get, "/other/app.js").is_not_found());
+    }
+
+    #[test]
+    fn test_spa_fallback() {
+        let mut router = Router::new();
+        let html = Some("text/html,application/xhtml+xml,*/*;q=0.8");
+        assert_eq!(None, router.fallback(RequestKind::Get, "/app/settings", html));
+
+        router.spa_fallback = Some("dist/index.html".to_string());
+        assert_eq!(Some("dist/index.html"), router.fallback(RequestKind::Get, "/app/settings/profile", html));
+        assert_eq!(Some("dist/index.html"), router.fallback(RequestKind::Head, "/", html));
+        assert_eq!(None, router.fallback(RequestKind::Get, "/static/missing.js", html));
+        assert_eq!(None, router.fallback(RequestKind::Get, "/app/settings", Some("application/json")));
+        assert_eq!(None, router.fallback(RequestKind::Get, "/app/settings", None));
+        assert_eq!(None, router.fallback(RequestKind::Get, "/app/settings", Some("text/html;q=0, */*")));
+        assert_eq!(None, router.fallback(RequestKind::Get, "/app/settings", Some("text/htmlx")));
+        assert_eq!(Some("dist/index.html"), router.fallback(RequestKind::Get, "/app/settings", Some("TEXT/HTML; q=0.5")));
+        assert_eq!(None, router.fallback(RequestKind::Post, "/app/settings", html));
+    }
+
+    #[test]
+    fn test_refresh_static_path() {
+        let test_dir = TempDir::new("rhs-tests").unwrap();
+        let dir_name = test_dir.path().file_name().unwrap().to_str().unwrap().to_string();
+        File::create(test_dir.path().join("app.js")).unwrap();
+        let shared = SharedRouter::new(Router::from(test_dir.path().to_str().unwrap()));
+        let before = shared.load();
+
+        ::std::fs::create_dir(test_dir.path().join("sub")).unwrap();
+        File::create(test_dir.path().join("sub").join("new.css")).unwrap();
+        File::create(test_dir.path().join("app.js.gz")).unwrap();
+        File::create(test_dir.path().join(".swp")).unwrap();
+        shared.update(|router| {
+            router.refresh_static_path(&test_dir.path().join("sub"));
+            router.refresh_static_path(&test_dir.path().join("app.js.gz"));
+            router.refresh_static_path(&test_dir.path().join(".swp"));
+        });
+        let router = shared.load();
+        assert!(router.is_static_content(&format!("/{}/sub/new.css", dir_name)));
+        assert!(!router.is_static_content(&format!("/{}/app.js.gz", dir_name)));
+        assert_eq!(1, router.precompressed(&router.static_routes[&format!("/{}/app.js", dir_name)]).len());
+        assert!(!router.is_static_content(&format!("/{}/.swp", dir_name)));
+        assert!(!before.is_static_content(&format!("/{}/sub/new.css", dir_name)));
+
+        ::std::fs::remove_dir_all(test_dir.path().join("sub")).unwrap();
+        ::std::fs::remove_file(test_dir.path().join("app.js")).unwrap();
+        shared.update(|router| {
+            router.refresh_static_path(&test_dir.path().join("sub"));
+            router.refresh_static_path(&test_dir.path().join("app.js"));
+        });
+        let router = shared.load();
+        assert!(!router.is_static_content(&format!("/{}/sub/new.css", dir_name)));
+        assert!(!router.static_dirs.contains_key(&format!("/{}/sub", dir_name)));
+        assert!(router.is_static_content(&format!("/{}/app.js.gz", dir_name)));
+        assert!(router.precompressed.is_empty());
+    }
+
+    #[test]
+    fn test_route_checking() {
+
+        let test_dir = TempDir::new("rhs-tests").unwrap();
+        let path_str = test_dir.path().file_name().unwrap().to_str().unwrap();
+        let filepath = test_dir.path().join("test.txt");
+        let _ = File::create(filepath).unwrap();
+
+        let router = Router::from(test_dir.path().to_str().unwrap());
+        println!("path prefix in route: {}", path_str);
+        println!("routes: {:?}", router.static_routes);
+        assert!(router.is_static_content(&format!("/{}/test.txt", path_str)));
+        assert!(!router.is_static_content(&format!("/{}/this_does_not_exist.txt", path_str)));
+    }
+
+    #[test]
+    fn test_preserve_directory_structure() {
+        let test_dir = TempDir::new("rhs-tests")
+            .expect("Could not create test dir");
+        let test_dir_name = test_dir.path().file_name().unwrap().to_str().unwrap();
+        let path = test_dir.path();
+        let sub_dir = TempDir::new_in(&test_dir, "foo")
+            .expect("Could not create sub dir");
+        let sub_dir_name = sub_dir.path().file_name().unwrap().to_str().unwrap();
+        let file_name = sub_dir.path().join("test.txt");
+        println!("{:?}", file_name);
+        let _ = File::create(file_name.as_path()).unwrap();
+
+        let router = Router::from(path.to_str().unwrap());
+        println!("routes: {:?}", router.static_routes);
+
+        assert!(
+            router
+            .is_static_content(
+                &format!("/{test_dir}/{sub_dir}/test.txt", 
+                         test_dir=test_dir_name,
+                         sub_dir=sub_dir_name)));
+
+    }
+
+    #[test]
+    fn test_method_routing() {
+        let mut router = Router::new();
+        router.static_routes.insert("/index.html".to_string(), "static/index.html".to_string());
+        let handler: Arc<Handler> = Arc::new(|_: &::request::Request| ::response::Response::http_ok(String::new()));
+        router.register_handler("/users/:id", Some(RequestKind::Get), handler.clone()).unwrap();
+        router.register_handler("/users/:id", Some(RequestKind::Delete), handler.clone()).unwrap();
+        router.register_handler("/any", None, handler).unwrap();
+
+        let allowed = |m: RouteMatch| match m {
+            RouteMatch::MethodNotAllowed(allow) | RouteMatch::Options(allow) => allow,
+            _ => panic!("Expected the allowed methods"),
+        };
+
+        assert!(match router.route(RequestKind::Head, "/index.html") { RouteMatch::Static(_) => true, _ => false });
+        assert_eq!("GET, HEAD, OPTIONS", allowed(router.route(RequestKind::Post, "/index.html")));
+        assert!(match router.route(RequestKind::Head, "/users/1") { RouteMatch::Handler(..) => true, _ => false });
+        assert_eq!("GET, HEAD, DELETE, OPTIONS", allowed(router.route(RequestKind::Put, "/users/1")));
+        assert_eq!("GET, HEAD, DELETE, OPTIONS", allowed(router.route(RequestKind::Options, "/users/1")));
+        assert!(match router.route(RequestKind::Put, "/any") { RouteMatch::Handler(..) => true, _ => false });
+        assert!(match router.route(RequestKind::Get, "/missing") { RouteMatch::NotFound => true, _ => false });
+    }
+
+    #[test]
+    fn test_encoded_handler_params() {
+        let mut router = Router::new();
+        let handler: Arc<Handler> = Arc::new(|_: &::request::Request| ::response::Response::http_ok(String::new()));
+        router.register_handler("/users/:id/posts", None, handler.clone()).unwrap();
+        router.register_handler("/users/:id/:x/posts", None, handler.clone()).unwrap();
+        router.register_handler("/caf\u{e9}/:name", None, handler).unwrap();
+
+        let params = |m: RouteMatch| match m {
+            RouteMatch::Handler(_, params) => params.iter().map(|&(_, ref v)| v.clone()).collect::<Vec<String>>(),
+            _ => panic!("Expected a handler"),
+        };
+
+        assert_eq!(vec!["a/b"], params(router.route(RequestKind::Get, "/users/a%2Fb/posts")));
+        assert_eq!(vec!["a", "b"], params(router.route(RequestKind::Get, "/users/a/b/posts")));
+        assert_eq!(vec!["100%", "x y"], params(router.route(RequestKind::Get, "/users/100%25/x%20y/posts")));
+        assert_eq!(vec!["cr\u{e8}me"], params(router.route(RequestKind::Get, "/caf%C3%A9/cr%C3%A8me")));
+    }
+}
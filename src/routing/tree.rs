@@ -0,0 +1,462 @@
+//! A radix tree matching request paths against route patterns.
+//!
+//! Patterns are made of static text and whole segment parameters:
+//!
+//! * `/users/:id` matches any non empty segment and captures it as `id`.
+//! * `/users/:id<int>` only matches segments satisfying the constraint. The
+//!   constraints are `int`, `alpha`, `alnum` and `uuid`.
+//! * `/files/*rest` matches the rest of the path, including `/`, and must end
+//!   the pattern.
+//!
+//! When several patterns match a path the most specific wins: at every
+//! position static text is tried before parameters and parameters before
+//! wildcards. Constrained parameters are tried before unconstrained ones.
+
+use std::error::Error;
+use std::fmt;
+
+/// Stores values by route pattern and looks them up by path.
+#[derive(Clone)]
+pub struct RouteTree<T> {
+    root: Node<T>,
+}
+
+/// Errors that can occur while adding a route pattern.
+#[derive(Debug, PartialEq)]
+pub enum RouteError {
+    /// The pattern did not begin with `/`.
+    MissingLeadingSlash,
+    /// A `:` or `*` was not followed by a name, or a parameter did not span a
+    /// whole segment.
+    InvalidParameter(String),
+    /// The constraint of a parameter was not one of the known constraints.
+    UnknownConstraint(String),
+    /// A wildcard was not at the end of the pattern.
+    WildcardNotLast,
+    /// A wildcard with a different name was already registered at the same
+    /// position.
+    ConflictingWildcard(String),
+}
+
+/// A constraint a parameter's segment must satisfy.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Constraint {
+    Int,
+    Alpha,
+    Alnum,
+    Uuid,
+}
+
+/// A piece of a parsed pattern.
+#[derive(Debug, PartialEq)]
+enum Piece<'a> {
+    Static(&'a str),
+    Param(&'a str, Option<Constraint>),
+    Wildcard(&'a str),
+}
+
+#[derive(Clone)]
+struct Node<T> {
+    /// The static text matched by this node. Empty for the root.
+    prefix: String,
+    value: Option<T>,
+    /// Static children, no two share a first byte.
+    children: Vec<Node<T>>,
+    params: Vec<ParamNode<T>>,
+    wildcard: Option<Box<WildcardNode<T>>>,
+}
+
+#[derive(Clone)]
+struct ParamNode<T> {
+    name: String,
+    constraint: Option<Constraint>,
+    node: Node<T>,
+}
+
+#[derive(Clone)]
+struct WildcardNode<T> {
+    name: String,
+    value: Option<T>,
+}
+
+impl<T> RouteTree<T> {
+    pub fn new() -> RouteTree<T> {
+        RouteTree {
+            root: Node::new(String::new()),
+        }
+    }
+
+    /// Adds a value for the pattern. A value already stored for the same
+    /// pattern is replaced.
+    #[cfg(test)]
+    pub fn insert(&mut self, pattern: &str, value: T) -> Result<(), RouteError> {
+        *self.entry(pattern)? = Some(value);
+        Ok(())
+    }
+
+    /// Returns the value stored for the pattern, adding it with `default` if
+    /// there is none.
+    pub fn get_or_insert_with<F>(&mut self, pattern: &str, default: F) -> Result<&mut T, RouteError>
+        where F: FnOnce() -> T
+    {
+        let entry = self.entry(pattern)?;
+        if entry.is_none() {
+            *entry = Some(default());
+        }
+        Ok(entry.as_mut().unwrap())
+    }
+
+    /// Returns the slot holding the value of the pattern, creating the nodes
+    /// leading to it.
+    fn entry(&mut self, pattern: &str) -> Result<&mut Option<T>, RouteError> {
+        let pieces = parse_pattern(pattern)?;
+        self.root.entry(&pieces)
+    }
+
+    /// Finds the value of the most specific pattern matching `path`. Returns
+    /// the value and the captured parameters in the order they appear in the
+    /// pattern.
+    pub fn find(&self, path: &str) -> Option<(&T, Vec<(String, String)>)> {
+        let mut params = Vec::new();
+        self.root.find(path, &mut params).map(|value| {
+            let params = params.into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+            (value, params)
+        })
+    }
+}
+
+impl<T> Node<T> {
+    fn new(prefix: String) -> Node<T> {
+        Node {
+            prefix,
+            value: None,
+            children: Vec::new(),
+            params: Vec::new(),
+            wildcard: None,
+        }
+    }
+
+    fn entry(&mut self, pieces: &[Piece]) -> Result<&mut Option<T>, RouteError> {
+        let (piece, rest) = match pieces.split_first() {
+            Some(split) => split,
+            None => return Ok(&mut self.value),
+        };
+
+        match *piece {
+            Piece::Static(text) => self.static_entry(text, rest),
+            Piece::Param(name, constraint) => {
+                let index = match self.params.iter()
+                    .position(|p| p.name == name && p.constraint == constraint) {
+                    Some(index) => index,
+                    None => {
+                        self.params.push(ParamNode {
+                            name: name.to_string(),
+                            constraint,
+                            node: Node::new(String::new()),
+                        });
+                        // Keep constrained parameters ahead of unconstrained
+                        // ones. The sort is stable so registration order is
+                        // kept otherwise.
+                        self.params.sort_by_key(|p| p.constraint.is_none());
+                        self.params.iter()
+                            .position(|p| p.name == name && p.constraint == constraint)
+                            .unwrap()
+                    }
+                };
+                self.params[index].node.entry(rest)
+            }
+            Piece::Wildcard(name) => {
+                if let Some(ref wildcard) = self.wildcard {
+                    if wildcard.name != name {
+                        return Err(RouteError::ConflictingWildcard(name.to_string()));
+                    }
+                }
+                let wildcard = self.wildcard.get_or_insert_with(|| Box::new(WildcardNode {
+                    name: name.to_string(),
+                    value: None,
+                }));
+                Ok(&mut wildcard.value)
+            }
+        }
+    }
+
+    /// Finds the node for static text below this node, splitting a child when
+    /// the text only shares part of its prefix. Children never share a first
+    /// character, but may share the first bytes of one.
+    fn static_entry(&mut self, text: &str, rest: &[Piece]) -> Result<&mut Option<T>, RouteError> {
+        let found = self.children.iter()
+            .map(|c| common_prefix(&c.prefix, text))
+            .enumerate()
+            .find(|&(_, common)| common > 0);
+        let (index, common) = match found {
+            Some(found) => found,
+            None => {
+                self.children.push(Node::new(text.to_string()));
+                let child = self.children.last_mut().unwrap();
+                return child.entry(rest);
+            }
+        };
+
+        let child = &mut self.children[index];
+        if common < child.prefix.len() {
+            child.split(common);
+        }
+        if common == text.len() {
+            child.entry(rest)
+        } else {
+            child.static_entry(&text[common..], rest)
+        }
+    }
+
+    /// Splits this node so it only matches the first `at` bytes of its
+    /// prefix. Everything else moves to a new child.
+    fn split(&mut self, at: usize) {
+        let suffix = self.prefix.split_off(at);
+        let mut child = Node::new(suffix);
+        child.value = self.value.take();
+        child.children = self.children.drain(..).collect();
+        child.params = self.params.drain(..).collect();
+        child.wildcard = self.wildcard.take();
+        self.children.push(child);
+    }
+
+    /// Matches the remainder of a path below this node, backtracking when a
+    /// more specific branch fails further down.
+    fn find<'a, 'p>(&'a self, path: &'p str, params: &mut Vec<(&'a str, &'p str)>) -> Option<&'a T> {
+        if path.is_empty() {
+            if let Some(ref value) = self.value {
+                return Some(value);
+            }
+        }
+
+        for child in &self.children {
+            if path.starts_with(child.prefix.as_str()) {
+                if let Some(value) = child.find(&path[child.prefix.len()..], params) {
+                    return Some(value);
+                }
+            }
+        }
+
+        let end = path.find('/').unwrap_or(path.len());
+        let segment = &path[..end];
+        if !segment.is_empty() {
+            for param in &self.params {
+                if param.constraint.map_or(true, |c| c.matches(segment)) {
+                    params.push((&param.name, segment));
+                    if let Some(value) = param.node.find(&path[end..], params) {
+                        return Some(value);
+                    }
+                    params.pop();
+                }
+            }
+        }
+
+        if let Some(ref wildcard) = self.wildcard {
+            if let Some(ref value) = wildcard.value {
+                params.push((&wildcard.name, path));
+                return Some(value);
+            }
+        }
+
+        None
+    }
+}
+
+impl Constraint {
+    fn from(name: &str) -> Option<Constraint> {
+        match name {
+            "int" => Some(Constraint::Int),
+            "alpha" => Some(Constraint::Alpha),
+            "alnum" => Some(Constraint::Alnum),
+            "uuid" => Some(Constraint::Uuid),
+            _ => None,
+        }
+    }
+
+    fn matches(&self, segment: &str) -> bool {
+        match *self {
+            Constraint::Int => {
+                let digits = segment.trim_start_matches('-');
+                !digits.is_empty() && digits.len() + 1 >= segment.len()
+                    && digits.bytes().all(|b| b.is_ascii_digit())
+            }
+            Constraint::Alpha => segment.bytes().all(|b| b.is_ascii_alphabetic()),
+            Constraint::Alnum => segment.bytes().all(|b| b.is_ascii_alphanumeric()),
+            Constraint::Uuid => {
+                segment.len() == 36 && segment.bytes().enumerate().all(|(i, b)| match i {
+                    8 | 13 | 18 | 23 => b == b'-',
+                    _ => b.is_ascii_hexdigit(),
+                })
+            }
+        }
+    }
+}
+
+/// Splits a pattern into static text, parameters and a wildcard.
+fn parse_pattern(pattern: &str) -> Result<Vec<Piece>, RouteError> {
+    if !pattern.starts_with('/') {
+        return Err(RouteError::MissingLeadingSlash);
+    }
+
+    let mut pieces = Vec::new();
+    let mut static_start = 0;
+    let mut segment_start = 0;
+    let segments: Vec<&str> = pattern.split('/').collect();
+    for (i, segment) in segments.iter().enumerate() {
+        if segment.starts_with(':') {
+            let (name, constraint) = match segment.find('<') {
+                Some(open) if segment.ends_with('>') => {
+                    let constraint = &segment[open + 1..segment.len() - 1];
+                    let constraint = Constraint::from(constraint)
+                        .ok_or_else(|| RouteError::UnknownConstraint(constraint.to_string()))?;
+                    (&segment[1..open], Some(constraint))
+                }
+                Some(_) => return Err(RouteError::InvalidParameter(segment.to_string())),
+                None => (&segment[1..], None),
+            };
+            if !is_valid_name(name) {
+                return Err(RouteError::InvalidParameter(segment.to_string()));
+            }
+            pieces.push(Piece::Static(&pattern[static_start..segment_start]));
+            pieces.push(Piece::Param(name, constraint));
+            static_start = segment_start + segment.len();
+        } else if segment.starts_with('*') {
+            if i != segments.len() - 1 {
+                return Err(RouteError::WildcardNotLast);
+            }
+            let name = &segment[1..];
+            if !is_valid_name(name) {
+                return Err(RouteError::InvalidParameter(segment.to_string()));
+            }
+            pieces.push(Piece::Static(&pattern[static_start..segment_start]));
+            pieces.push(Piece::Wildcard(name));
+            static_start = pattern.len();
+        } else if segment.contains(|c| c == ':' || c == '*') {
+            return Err(RouteError::InvalidParameter(segment.to_string()));
+        }
+        segment_start += segment.len() + 1;
+    }
+    if static_start < pattern.len() {
+        pieces.push(Piece::Static(&pattern[static_start..]));
+    }
+
+    Ok(pieces.into_iter().filter(|p| *p != Piece::Static("")).collect())
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+/// Returns the length in bytes of the longest common prefix of `a` and `b`
+/// that ends on a character boundary.
+fn common_prefix(a: &str, b: &str) -> usize {
+    let mut common = a.bytes().zip(b.bytes()).take_while(|&(x, y)| x == y).count();
+    while !a.is_char_boundary(common) {
+        common -= 1;
+    }
+    common
+}
+
+impl RouteError {
+    fn message(&self) -> &str {
+        match *self {
+            RouteError::MissingLeadingSlash => "Route patterns must begin with /",
+            RouteError::InvalidParameter(_) => "Invalid parameter",
+            RouteError::UnknownConstraint(_) => "Unknown parameter constraint",
+            RouteError::WildcardNotLast => "A wildcard must be the last segment of a route",
+            RouteError::ConflictingWildcard(_) => "Conflicting wildcard",
+        }
+    }
+}
+
+impl Error for RouteError {
+    fn description(&self) -> &str {
+        self.message()
+    }
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RouteError::InvalidParameter(ref s) | RouteError::UnknownConstraint(ref s) |
+            RouteError::ConflictingWildcard(ref s) => write!(f, "{}: {:?}", self.message(), s),
+            _ => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(patterns: &[&'static str]) -> RouteTree<&'static str> {
+        let mut tree = RouteTree::new();
+        for pattern in patterns {
+            tree.insert(pattern, *pattern).unwrap();
+        }
+        tree
+    }
+
+    #[test]
+    fn test_static_routes_share_prefixes() {
+        let tree = tree(&["/users", "/user", "/users/new", "/u", "/"]);
+
+        for path in &["/users", "/user", "/users/new", "/u", "/"] {
+            assert_eq!(Some(*path), tree.find(path).map(|(v, _)| *v));
+        }
+        assert!(tree.find("/use").is_none());
+        assert!(tree.find("/users/").is_none());
+    }
+
+    #[test]
+    fn test_non_ascii_prefixes() {
+        // `é` and `è` share their first utf8 byte.
+        let tree = tree(&["/é", "/è", "/èa", "/éa"]);
+
+        for path in &["/é", "/è", "/èa", "/éa"] {
+            assert_eq!(Some(*path), tree.find(path).map(|(v, _)| *v));
+        }
+        assert!(tree.find("/e").is_none());
+    }
+
+    #[test]
+    fn test_parameters_and_wildcards() {
+        let tree = tree(&["/users/:id", "/users/:id/posts/:post", "/files/*rest"]);
+
+        let (value, params) = tree.find("/users/42/posts/7").unwrap();
+        assert_eq!("/users/:id/posts/:post", *value);
+        assert_eq!(vec![("id".to_string(), "42".to_string()), ("post".to_string(), "7".to_string())], params);
+
+        let (value, params) = tree.find("/files/css/site.css").unwrap();
+        assert_eq!("/files/*rest", *value);
+        assert_eq!(vec![("rest".to_string(), "css/site.css".to_string())], params);
+
+        assert!(tree.find("/users/").is_none());
+        assert!(tree.find("/users/42/posts").is_none());
+    }
+
+    #[test]
+    fn test_precedence() {
+        let tree = tree(&["/users/*rest", "/users/:name", "/users/:id<int>", "/users/new", "/users/:id/edit"]);
+        let find = |path| *tree.find(path).unwrap().0;
+
+        assert_eq!("/users/new", find("/users/new"));
+        assert_eq!("/users/:id<int>", find("/users/42"));
+        assert_eq!("/users/:name", find("/users/alice"));
+        assert_eq!("/users/:id/edit", find("/users/new/edit"));
+        assert_eq!("/users/*rest", find("/users/alice/photos"));
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        let mut tree = RouteTree::new();
+
+        assert_eq!(Err(RouteError::MissingLeadingSlash), tree.insert("users", ()));
+        assert_eq!(Err(RouteError::WildcardNotLast), tree.insert("/files/*rest/edit", ()));
+        assert_eq!(Err(RouteError::UnknownConstraint("float".to_string())), tree.insert("/:n<float>", ()));
+        assert!(tree.insert("/users/id:id", ()).is_err());
+        assert!(tree.insert("/users/:", ()).is_err());
+    }
+}
@@ -1,25 +1,47 @@
 //! The server module.
 
 extern crate native_tls;
+#[cfg(target_os = "linux")]
+extern crate libc;
 
-use std::{str, env};
-use std::net::{TcpListener, TcpStream, Shutdown};
-use std::io::{Read, Write};
-use std::sync::Arc;
+use std::borrow::Cow;
+use std::env;
+use std::net::{TcpListener, TcpStream, Shutdown, SocketAddr, IpAddr, Ipv4Addr, Ipv6Addr};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::result::Result;
 use std::fs::File;
 use std::thread;
 use std::path::{Path, PathBuf};
 use std::error::Error;
 use std::fmt;
+use std::time::Duration;
+#[cfg(target_os = "linux")]
+use std::ptr;
+use std::panic::{self, AssertUnwindSafe};
 
 use self::native_tls::{TlsStream, TlsAcceptor, Pkcs12};
 
+use autoindex;
+use cache::CachePolicy;
+use compression::{negotiate, merge_vary, Encoding};
+use conditional::{evaluate, file_etag, Precondition};
 use config::Config;
-use request::Request;
-use response::Response;
-use routing::Router;
+use handler::Handler;
+use livereload::{LiveReload, LIVERELOAD_PATH};
+use mime::MimeTypes;
+use mount::Mount;
+use reader::{RequestReader, ReadError, DEFAULT_MAX_BODY_SIZE};
+use request::{Request, RequestKind, ParseError, ALLOWED_METHODS};
+use range::{parse_range, if_range_matches, Ranges};
+use response::{Response, Body, Segment, copy_file};
+use status::StatusCode;
+use routing::{Router, RouteMatch, SharedRouter};
 use threadpool::ThreadPool;
+use utils::date::format_http_date;
+use utils::url::percent_encode_path;
+use watcher;
 //#[cfg(feature="wsgi")]
 use wsgi::application::Application;
 
@@ -29,11 +51,21 @@ pub struct Server {
     thread_pool: ThreadPool,
     router: Router,
     dir: PathBuf,
+    shutdown: ShutdownHandle,
+}
+
+/// A handle used to stop a running server, e.g. from a signal handler or a
+/// test. Created with `Server::shutdown_handle`.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    stopping: Arc<AtomicBool>,
+    addresses: Arc<Mutex<Vec<SocketAddr>>>,
 }
 
 #[derive(Debug)]
 enum ServerError {
     ClientClosedConnection(&'static str),
+    MalformedRequest(ReadError),
 }
 
 type ServerResult<T> = Result<T, ServerError>;
@@ -63,9 +95,133 @@ impl Close for TcpStream {
     }
 }
 
+/// For types that can limit how long a read may block.
+trait Timeout {
+    fn set_timeout(&self, dur: Option<Duration>) -> io::Result<()>;
+}
+
+/// The timeout is set on the underlying stream of a `TlsStream`.
+impl<T: Read + Write + Timeout> Timeout for TlsStream<T> {
+    fn set_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.get_ref().set_timeout(dur)
+    }
+}
+impl Timeout for TcpStream {
+    fn set_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.set_read_timeout(dur)
+    }
+}
+
+/// For types that can send part of a file.
+trait SendFile {
+    /// Sends `length` bytes from the current position of the file.
+    fn send_file(&mut self, file: &File, length: u64) -> io::Result<()>;
+}
+
+/// TLS connections encrypt the file so it is copied through userspace.
+impl<T: Read + Write> SendFile for TlsStream<T> {
+    fn send_file(&mut self, file: &File, length: u64) -> io::Result<()> {
+        copy_file(file, self, length)
+    }
+}
+
+/// Plain TCP connections on Linux use `sendfile(2)` so the file is copied by
+/// the kernel without passing through userspace.
+#[cfg(target_os = "linux")]
+impl SendFile for TcpStream {
+    fn send_file(&mut self, file: &File, length: u64) -> io::Result<()> {
+        use std::os::unix::io::AsRawFd;
+
+        let mut remaining = length;
+        while remaining > 0 {
+            let count = remaining.min(MAX_SENDFILE_SIZE) as usize;
+            // A null offset sends from, and advances, the file's position.
+            let sent = unsafe {
+                libc::sendfile(self.as_raw_fd(), file.as_raw_fd(), ptr::null_mut(), count)
+            };
+            if sent < 0 {
+                let e = io::Error::last_os_error();
+                match e.raw_os_error() {
+                    Some(libc::EINTR) => continue,
+                    // The file doesn't support sendfile, nothing was sent.
+                    Some(libc::EINVAL) | Some(libc::ENOSYS) if remaining == length => {
+                        return copy_file(file, self, length);
+                    }
+                    _ => return Err(e),
+                }
+            }
+            if sent == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "File shorter than its length"));
+            }
+            remaining -= sent as u64;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl SendFile for TcpStream {
+    fn send_file(&mut self, file: &File, length: u64) -> io::Result<()> {
+        copy_file(file, self, length)
+    }
+}
+
+/// The most bytes passed to a single `sendfile` call.
+#[cfg(target_os = "linux")]
+const MAX_SENDFILE_SIZE: u64 = 1 << 30;
+
+/// For connections that can be handed over to be written to from elsewhere.
+trait Detach {
+    /// Returns a handle to the underlying socket, `None` if writes need state
+    /// owned by the connection.
+    fn detach(&self) -> Option<TcpStream>;
+}
+
+/// Writes to TLS connections need the session so they can't be detached.
+impl<T: Read + Write> Detach for TlsStream<T> {
+    fn detach(&self) -> Option<TcpStream> {
+        None
+    }
+}
+impl Detach for TcpStream {
+    fn detach(&self) -> Option<TcpStream> {
+        self.try_clone().ok()
+    }
+}
+
 /// A trait for TCP connections. Includes TLS and reguler TCP.
-trait Connection: Read + Write + Close {}
-impl<T> Connection for T where T: Read + Write + Close {}
+trait Connection: Read + Write + Close + Timeout + SendFile + Detach {}
+impl<T> Connection for T where T: Read + Write + Close + Timeout + SendFile + Detach {}
+
+/// How long an idle connection is kept open in seconds.
+const DEFAULT_KEEP_ALIVE_TIMEOUT: u64 = 5;
+
+/// The number of requests served on a connection before it is closed.
+const DEFAULT_MAX_REQUESTS_PER_CONNECTION: usize = 100;
+
+/// How long to wait for requests in flight when shutting down in seconds.
+const DEFAULT_SHUTDOWN_GRACE_PERIOD: u64 = 10;
+
+/// Per connection settings taken from the config.
+#[derive(Clone, Copy)]
+struct ConnectionSettings {
+    max_body_size: usize,
+    keep_alive_timeout: Duration,
+    max_requests: usize,
+}
+
+impl ConnectionSettings {
+    fn from_config(config: &Config) -> ConnectionSettings {
+        let timeout = config.keep_alive_timeout.unwrap_or(DEFAULT_KEEP_ALIVE_TIMEOUT);
+        ConnectionSettings {
+            max_body_size: config.max_body_size.unwrap_or(DEFAULT_MAX_BODY_SIZE),
+            keep_alive_timeout: Duration::from_secs(timeout),
+            max_requests: config.max_requests_per_connection
+                .unwrap_or(DEFAULT_MAX_REQUESTS_PER_CONNECTION)
+                .max(1),
+        }
+    }
+}
 
 pub trait ServerApplication {
     fn create(app_string: Option<&String>, port: &str) -> Option<Self> where Self: ::std::marker::Sized;
@@ -88,10 +244,46 @@ impl fmt::Display for InternalServerError {
     }
 }
 
-impl Server {
-    // TODO: Implement a server shutdown method that stops tcp listeners
-    // this can be done with mpsc I believe.
+impl ShutdownHandle {
+    fn new() -> ShutdownHandle {
+        ShutdownHandle {
+            stopping: Arc::new(AtomicBool::new(false)),
+            addresses: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Stops the server from accepting new connections. `Server::serve` returns
+    /// once the requests in flight have been handled or the grace period has
+    /// ended.
+    pub fn shutdown(&self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        for address in self.addresses.lock().unwrap().iter() {
+            // Wakes up the listener blocked in accept so it sees the flag
+            let _ = TcpStream::connect(address);
+        }
+    }
+
+    /// Checks if a shutdown has been requested.
+    pub fn is_stopping(&self) -> bool {
+        self.stopping.load(Ordering::SeqCst)
+    }
+
+    /// Remembers the address of a listener so it can be woken up on shutdown.
+    fn register(&self, listener: &TcpListener) {
+        if let Ok(mut address) = listener.local_addr() {
+            if address.ip().is_unspecified() {
+                let loopback = match address {
+                    SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                    SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1)),
+                };
+                address.set_ip(loopback);
+            }
+            self.addresses.lock().unwrap().push(address);
+        }
+    }
+}
 
+impl Server {
     /// Creates a server with provided config object.
     pub fn from_config(config: Config) -> Server {
         debug!("Initializing a server with config: \n{:#?}", config);
@@ -100,8 +292,44 @@ impl Server {
 
         let static_folder = config.static_folder.clone();
 
-        let router = Router::from(&static_folder
+        let mut router = Router::from(&static_folder
                                   .unwrap_or_else(|| "static".to_string()));
+        if let Some(ref mime_types) = config.mime_types {
+            router.mime_types = MimeTypes::with_overrides(mime_types);
+        }
+        if let Some(ref cache_rules) = config.cache_rules {
+            router.cache_policy = CachePolicy::new(cache_rules.clone());
+        }
+        if let Some(ref compression) = config.compression {
+            router.compression = compression.clone();
+        }
+        router.autoindex = config.autoindex.unwrap_or(false);
+        if let Some(ref index_files) = config.index_files {
+            router.index_files = index_files.clone();
+        }
+        if let Some(trailing_slash) = config.trailing_slash {
+            router.trailing_slash = trailing_slash;
+        }
+        if config.dev.unwrap_or(false) {
+            if config.https() {
+                warn!("Live reload is only available over HTTP, not on the HTTPS port");
+            }
+            router.live_reload = Some(LiveReload::new());
+        }
+        if let Some(ref mounts) = config.mounts {
+            for mount in mounts {
+                if !Path::new(&mount.root).is_dir() {
+                    warn!("Mount {} is not a directory", mount.root);
+                }
+            }
+            router.mounts = mounts.clone();
+        }
+        if let Some(ref spa_fallback) = config.spa_fallback {
+            if !Path::new(spa_fallback).is_file() {
+                warn!("SPA fallback {} is not a file", spa_fallback);
+            }
+            router.spa_fallback = Some(spa_fallback.clone());
+        }
 
         // This unwrap should probably changed to a default directory
         let dir = env::current_dir().unwrap();
@@ -111,37 +339,123 @@ impl Server {
             thread_pool,
             router,
             dir,
+            shutdown: ShutdownHandle::new(),
         }
     }
 
+    /// Returns a handle that can stop the server once `serve` has been called.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Stops the server. See `ShutdownHandle::shutdown`.
+    pub fn shutdown(&self) {
+        self.shutdown.shutdown();
+    }
+
     /// Registers the provided directory's contents to be served over HTTP.
     pub fn serve_directory(&mut self, dir: &str) {
         trace!("Registering static routes for: {}", dir);
         self.router.register_static_routes(dir);
     }
 
+    /// Serves the directory at the URL prefix, finding files as they are
+    /// requested instead of registering routes. See `Mount`.
+    pub fn mount(&mut self, url: &str, root: &str) {
+        trace!("Mounting {} at {}", root, url);
+        self.router.mounts.push(Mount::new(url, root));
+    }
+
+    /// Registers a handler for requests matching `pattern` with any method.
+    /// Static files take precedence over handlers and handlers over the WSGI
+    /// application.
+    ///
+    /// Patterns may contain parameters (`/users/:id`), constrained parameters
+    /// (`/users/:id<int>`) and a trailing wildcard (`/files/*rest`). The
+    /// captured values are available from `Request::param`. When several
+    /// patterns match, static text beats parameters and parameters beat
+    /// wildcards.
+    ///
+    /// # Panics
+    /// Panics if the pattern is invalid.
+    pub fn route<H: Handler + 'static>(&mut self, pattern: &str, handler: H) {
+        self.register_handler(pattern, None, Arc::new(handler));
+    }
+
+    /// Registers a handler for requests matching `pattern` with the method.
+    /// Requests to the pattern with another method receive a 405 response and
+    /// `OPTIONS` requests are answered with the allowed methods. A `GET`
+    /// handler also serves `HEAD` requests.
+    ///
+    /// # Panics
+    /// Panics if the pattern is invalid.
+    pub fn route_method<H: Handler + 'static>(&mut self, method: RequestKind, pattern: &str, handler: H) {
+        self.register_handler(pattern, Some(method), Arc::new(handler));
+    }
+
+    /// Registers a `GET` handler. See `route_method`.
+    pub fn get<H: Handler + 'static>(&mut self, pattern: &str, handler: H) {
+        self.route_method(RequestKind::Get, pattern, handler);
+    }
+
+    /// Registers a `POST` handler. See `route_method`.
+    pub fn post<H: Handler + 'static>(&mut self, pattern: &str, handler: H) {
+        self.route_method(RequestKind::Post, pattern, handler);
+    }
+
+    /// Registers a `PUT` handler. See `route_method`.
+    pub fn put<H: Handler + 'static>(&mut self, pattern: &str, handler: H) {
+        self.route_method(RequestKind::Put, pattern, handler);
+    }
+
+    /// Registers a `DELETE` handler. See `route_method`.
+    pub fn delete<H: Handler + 'static>(&mut self, pattern: &str, handler: H) {
+        self.route_method(RequestKind::Delete, pattern, handler);
+    }
+
+    fn register_handler(&mut self, pattern: &str, method: Option<RequestKind>, handler: Arc<Handler>) {
+        trace!("Registering handler for: {}", pattern);
+        if let Err(e) = self.router.register_handler(pattern, method, handler) {
+            panic!("Invalid route pattern {:?}: {}", pattern, e);
+        }
+    }
+
     /// The main loop of the program.
     /// Listens on specified host and port and accepts incoming connections
     /// If a pkcs12 is provided the server will listen on port 8443 for HTTPS
     /// requests as well. The pkcs12 password can be provided in the config or 
     /// as an environment variable.
     /// Takes connections and adds them and their handler to the threadpool.
+    /// Returns after a shutdown is requested through a `ShutdownHandle` and the
+    /// connections already accepted have been handled, waiting at most the
+    /// configured grace period.
     pub fn serve(&self) {
         let listener = TcpListener::bind(format!("{}:{}", &self.config.host, &self.config.port)).expect("Could not start listener on specified host address/port");
+        self.shutdown.register(&listener);
         info!("Running on host: {}", &self.config.host);
         info!("Running on port: {}", &self.config.port);
 
         let app = Arc::new(Application::create(self.config.app.as_ref(), &self.config.port)); // This will probably be changed
-        let shared_router = Arc::new(self.router.clone());
+        let settings = ConnectionSettings::from_config(&self.config);
+        let shared_router = SharedRouter::new(self.router.clone());
+        if self.config.watch.unwrap_or(false) || self.config.dev.unwrap_or(false) {
+            let poll_interval = self.config.watch_poll_interval.map(Duration::from_millis);
+            if let Err(e) = watcher::watch(shared_router.clone(), poll_interval, Arc::clone(&self.shutdown.stopping)) {
+                error!("Unable to watch the static folders: {:?}", e);
+            }
+        }
         
         let https: bool = self.config.https();
+        let mut https_thread = None;
         
         if https {
             
             info!("HTTPS enabled. Running on {}:8443", &self.config.host);
             let second_listener = TcpListener::bind(format!("{}:8443", &self.config.host)).expect("Unable to create TCP listener on specified HTTPS port.");
+            self.shutdown.register(&second_listener);
+            let https_shutdown = self.shutdown.clone();
             let https_app = Arc::clone(&app);
-            let https_router = Arc::clone(&shared_router);
+            let https_router = shared_router.clone();
 
             // TLS 
             let cert_filename = self.config.https_cert.clone().unwrap();
@@ -167,12 +481,16 @@ impl Server {
 
             let executor = self.thread_pool.create_executor();
 
-            thread::spawn(move || {
+            https_thread = Some(thread::spawn(move || {
                 for stream in second_listener.incoming() {
+                    if https_shutdown.is_stopping() {
+                        break;
+                    }
                     match stream {
                         Ok(stream) => {
                             let app_instance = Arc::clone(&https_app);
-                            let router_instance = Arc::clone(&https_router);
+                            let router_instance = https_router.clone();
+                            let stopping = Arc::clone(&https_shutdown.stopping);
                             let acceptor = Arc::clone(&acceptor);
                             let mut stream = match acceptor.accept(stream) {
                                 Ok(stream) => stream,
@@ -186,7 +504,9 @@ impl Server {
                                 if let Err(e) = 
                                     handle_connection(&mut stream,
                                                       app_instance.as_ref(),
-                                                      &router_instance) {
+                                                      &router_instance,
+                                                      settings,
+                                                      &stopping) {
                                 error!("Error handling connection {:?}", e);
                                 };
                             });
@@ -195,16 +515,20 @@ impl Server {
                                            {:?}", e); }
                     }
                 }
-            });
+            }));
         }
 
         for stream in listener.incoming() {
+            if self.shutdown.is_stopping() {
+                break;
+            }
             match stream {
                 Ok(mut stream) => {
                     let app_instance = Arc::clone(&app);
-                    let router_instance = Arc::clone(&shared_router);
+                    let router_instance = shared_router.clone();
+                    let stopping = Arc::clone(&self.shutdown.stopping);
                     self.thread_pool.execute(move || {
-                        if let Err(e) = handle_connection(&mut stream, app_instance.as_ref(), &router_instance) {
+                        if let Err(e) = handle_connection(&mut stream, app_instance.as_ref(), &router_instance, settings, &stopping) {
                             error!("Error handling connection {:?}", e);
                         };
                     });
@@ -213,79 +537,361 @@ impl Server {
                                    {:?}", e); }
             }
         }
+
+        info!("No longer accepting connections. Waiting for requests in flight.");
+        if let Some(thread) = https_thread {
+            if thread.join().is_err() {
+                error!("HTTPS listener thread panicked");
+            }
+        }
+        let grace_period = self.config.shutdown_grace_period.unwrap_or(DEFAULT_SHUTDOWN_GRACE_PERIOD);
+        if !self.thread_pool.shutdown(Duration::from_secs(grace_period)) {
+            warn!("Requests were still in flight after the {}s grace period", grace_period);
+        }
+        info!("Server stopped");
     }
 }
 
 
 /// Handles each connection in its own thread.
-/// Passes the request to the application then once the application
-/// has created a response sends the response.
+/// Requests are read from the connection one at a time and each response is
+/// sent before the next request is read, so pipelined requests are answered in
+/// order. The connection stays open until the client asks for it to be closed,
+/// it is idle for longer than the keep-alive timeout or the maximum number of
+/// requests per connection has been served.
 // TODO: Make the app parameter generic for an Application trait (e.g. wsgi or 
 // something else)
 fn handle_connection<T: Connection>(stream: &mut T,
                                     app: &Option<Application>,
-                                    router: &Router) -> ServerResult<()>{
-
-    let mut buf = [0u8; 256]; // buffer size = 256 bytes
-    match stream.read(&mut buf) {
-        Ok(_) => { 
-            let data = str::from_utf8(&buf).expect("Could not convert request data to utf8");
-            let request = Request::from(String::from(data));
-            vprintln!("REQUEST: {}", request);
-            info!("Handling request: {}", request);
-            match stream.close(Shutdown::Read) { 
-                Ok(_) => {
-                    if router.is_static_content(&request.path) {
-                        let result = match serve_static_content(&request, router) {
-                            Ok(data) => {
-                            Response::http_ok_file(data).to_bytes()
-                        },
-                            Err(e) =>  {
-                                error!("Error loading static content {:?}", e);
-                                Response::server_error().to_bytes()
-                            }
-                        };
-                        if let Err(e) = stream.write(&result) {
-                            error!("Error writing to stream: {:?}", e);
-                        };
-                    } else {
-                        let result = app.clone()
-                            .map_or(Response::not_found().to_string(), |app| { 
-                                match app.handle_one_request(request){
-                                    Ok(v) => v,
-                                    Err(e) => {
-                                        error!("Application error: {:?}", e);
-                                        Response::server_error().to_string()
-                                    }
-                                }
-                            });
-                        if let Err(e) = stream.write(result.as_bytes()) {
-                            error!("Error writing to stream: {:?}", e);
-                        };
+                                    router: &SharedRouter,
+                                    settings: ConnectionSettings,
+                                    stopping: &AtomicBool) -> ServerResult<()>{
+
+    if let Err(e) = stream.set_timeout(Some(settings.keep_alive_timeout)) {
+        error!("Error setting connection timeout: {:?}", e);
+    }
+
+    let mut reader = RequestReader::new(settings.max_body_size);
+    let mut served = 0;
+    loop {
+        let request = match reader.read_request(stream) {
+            Ok(request) => request,
+            Err(ReadError::Closed) if served > 0 => return Ok(()),
+            Err(ReadError::Io(ref e)) if served > 0 && is_timeout(e) => {
+                debug!("Closing idle connection");
+                if let Err(e) = stream.close(Shutdown::Both) {
+                    error!("Error closing stream: {:?}", e);
+                }
+                return Ok(());
+            }
+            Err(ReadError::Closed) | Err(ReadError::UnexpectedEof) | Err(ReadError::Io(_)) => {
+                return Err(ServerError::ClientClosedConnection("Connection closed by client"));
+            }
+            Err(e) => {
+                // The rest of the stream can't be framed after a malformed
+                // request so the connection is closed after the error.
+                let mut response = error_response(&e);
+                response.set_keep_alive(false);
+                if let Err(e) = stream.write_all(&response.to_bytes()) {
+                    error!("Error writing to stream: {:?}", e);
+                }
+                if let Err(e) = stream.close(Shutdown::Both) {
+                    error!("Error closing stream: {:?}", e);
+                }
+                return Err(ServerError::MalformedRequest(e));
+            }
+        };
+        vprintln!("REQUEST: {}", request);
+        info!("Handling request: {}", request);
+
+        served += 1;
+        let keep_alive = request.keep_alive()
+            && served < settings.max_requests
+            && !stopping.load(Ordering::SeqCst);
+
+        let router = router.load();
+        let result = match router.live_reload {
+            Some(ref live_reload) if request.path == LIVERELOAD_PATH => {
+                let mut response = if request.kind != RequestKind::Get {
+                    Response::method_not_allowed("GET")
+                } else if let Some(socket) = stream.detach() {
+                    // The event stream stays open without a worker thread.
+                    match live_reload.subscribe(socket) {
+                        Ok(()) => debug!("{} browsers listening for reloads", live_reload.client_count()),
+                        Err(e) => error!("Error starting live reload: {:?}", e),
                     }
-                    if let Err(e) = stream.close(Shutdown::Write) {
-                        error!("Error closing stream: {:?}", e);
-                    } 
-                    vprintln!("Stream has been flushed");
-                    Ok(())
+                    return Ok(());
+                } else {
+                    // TLS connections can't be handed over, the browser stops
+                    // reconnecting when it gets an error.
+                    Response::error(StatusCode::NOT_IMPLEMENTED)
+                };
+                response.set_keep_alive(keep_alive);
+                write_response(stream, &response)
+            }
+            _ => respond(stream, request, app, &router, keep_alive),
+        };
+
+        if let Err(e) = result {
+            error!("Error writing to stream: {:?}", e);
+            return Err(ServerError::ClientClosedConnection("Error writing response"));
+        }
+        vprintln!("Stream has been flushed");
+
+        if !keep_alive {
+            if let Err(e) = stream.close(Shutdown::Write) {
+                error!("Error closing stream: {:?}", e);
+            }
+            return Ok(());
+        }
+    }
+}
+
+/// Creates the error response sent when a request can't be read.
+fn error_response(error: &ReadError) -> Response {
+    match *error {
+        ReadError::Parse(ParseError::MethodNotAllowed(_)) => Response::method_not_allowed(ALLOWED_METHODS),
+        ReadError::Parse(ref e) => Response::error(e.status()),
+        ReadError::HeadTooLarge => Response::request_header_fields_too_large(),
+        ReadError::PayloadTooLarge => Response::payload_too_large(),
+        ReadError::UnsupportedTransferEncoding => Response::error(StatusCode::NOT_IMPLEMENTED),
+        _ => Response::bad_request(),
+    }
+}
+
+/// Creates the response for a single request. Static content is served first,
+/// then registered handlers, then files in the mounts, then the application
+/// handles every other request. Page requests get the SPA fallback file if
+/// nothing else serves them, including the application answering with a 404.
+/// Bodies are compressed if the client accepts it. Responses to `HEAD`
+/// requests are created the same way as for `GET` and then have their body
+/// removed, keeping the headers describing it. Their body isn't compressed,
+/// only its compressed length is computed.
+fn respond<T: Connection>(stream: &mut T,
+                          request: Request,
+                          app: &Option<Application>,
+                          router: &Router,
+                          keep_alive: bool) -> io::Result<()> {
+    let head = request.kind == RequestKind::Head;
+    let accept_encoding = request.header("accept-encoding").map(|e| e.to_string());
+    let found = match router.route(request.kind, &request.raw_path) {
+        RouteMatch::NotFound => match router.route_mounted(request.kind, &request.raw_path) {
+            RouteMatch::NotFound if app.is_none() => router.fallback(request.kind, &request.path, request.header("accept"))
+                .map_or(RouteMatch::NotFound, |file| RouteMatch::Static(Cow::Borrowed(file))),
+            found => found,
+        },
+        found => found,
+    };
+    let mut response = match found {
+        RouteMatch::Static(file) => match serve_static_content(&request, &file, router) {
+            Ok(response) => response,
+            Err(e) => {
+                error!("Error loading static content {:?}", e);
+                Response::server_error()
+            }
+        },
+        RouteMatch::Directory(dir) => {
+            let has_parent = router.has_parent_dir(&request.path);
+            match autoindex::listing(&request, Path::new(dir.as_ref()), &request.path, has_parent) {
+                Ok(response) => response,
+                Err(e) => {
+                    error!("Error listing directory {:?}", e);
+                    Response::server_error()
                 }
+            }
+        }
+        RouteMatch::Handler(handler, params) => {
+            let mut request = request;
+            request.params = params;
+            // A panicking handler shouldn't take down the worker thread.
+            match panic::catch_unwind(AssertUnwindSafe(|| handler.handle(&request))) {
+                Ok(response) => response,
                 Err(_) => {
-                    Err(ServerError::ClientClosedConnection("Error closing read connection"))
+                    error!("Handler for {} panicked", request.path);
+                    Response::server_error()
                 }
             }
         }
-        Err(_) =>  Err(ServerError::ClientClosedConnection("Connection closed by client"))
+        RouteMatch::Options(allow) => Response::options(&allow),
+        RouteMatch::Redirect(path) => {
+            let mut location = percent_encode_path(&path);
+            if !request.query_string.is_empty() {
+                location.push('?');
+                location.push_str(&request.query_string);
+            }
+            match request.kind {
+                RequestKind::Get | RequestKind::Head => Response::moved_permanently(&location),
+                _ => Response::permanent_redirect(&location),
+            }
+        }
+        RouteMatch::MethodNotAllowed(allow) => Response::method_not_allowed(&allow),
+        RouteMatch::BadRequest => Response::bad_request(),
+        RouteMatch::Forbidden => Response::forbidden(),
+        RouteMatch::NotFound => match *app {
+            Some(ref app) => {
+                // Prepared while the request is still here, the page is served
+                // if the application has nothing for the path either.
+                let fallback = router.fallback(request.kind, &request.path, request.header("accept"))
+                    .map(|file| serve_static_content(&request, file, router));
+                match (app.handle_one_request(request), fallback) {
+                    (Ok(ref v), Some(page)) if application_status(v) == Some(404) => match page {
+                        Ok(response) => response,
+                        Err(e) => {
+                            error!("Error loading the SPA fallback {:?}", e);
+                            Response::server_error()
+                        }
+                    },
+                    (Ok(v), _) => {
+                        let v = set_connection_header(v, keep_alive).into_bytes();
+                        let v = router.compression.compress_raw(v, accept_encoding.as_deref());
+                        let v = if head { without_body(v) } else { v };
+                        stream.write_all(&v)?;
+                        return stream.flush();
+                    }
+                    (Err(e), _) => {
+                        error!("Application error: {:?}", e);
+                        Response::server_error()
+                    }
+                }
+            }
+            None => Response::not_found(),
+        },
+    };
+    if let Some(ref live_reload) = router.live_reload {
+        live_reload.inject(&mut response)?;
+    }
+    if head {
+        router.compression.compress_head(&mut response, accept_encoding.as_deref())?;
+        response.remove_body();
+    } else {
+        router.compression.compress_response(&mut response, accept_encoding.as_deref())?;
+    }
+    response.set_keep_alive(keep_alive);
+    write_response(stream, &response)
+}
+
+/// Writes a response to the stream. File bodies are sent with `send_file`.
+fn write_response<T: Connection>(stream: &mut T, response: &Response) -> io::Result<()> {
+    match *response.body() {
+        Body::File(ref file, length) => {
+            stream.write_all(response.headers_only().as_bytes())?;
+            stream.send_file(file, length)?;
+        }
+        Body::Segments(ref file, ref segments) => {
+            stream.write_all(response.headers_only().as_bytes())?;
+            for segment in segments {
+                match *segment {
+                    Segment::Bytes(ref bytes) => stream.write_all(bytes)?,
+                    Segment::File(range) => {
+                        let mut file = file;
+                        file.seek(SeekFrom::Start(range.start))?;
+                        stream.send_file(file, range.len())?;
+                    }
+                }
+            }
+        }
+        _ => response.write_to(stream)?,
     }
+    stream.flush()
 }
 
-/// Gets the absolute path of the file and reads it into a buffer. Returns 
-/// the buffer.
-fn serve_static_content(request: &Request, router: &Router) -> Result<Vec<u8>, ::std::io::Error> {
-    let abs_path = router.get(&request.path);
-    let mut buffer = Vec::new();
-    let mut file = File::open(&abs_path)?;
-    file.read_to_end(&mut buffer)?;
-    Ok(buffer)
+/// Removes the body from a response created by the application, keeping the
+/// status line and headers.
+fn without_body(mut response: Vec<u8>) -> Vec<u8> {
+    if let Some(i) = response.windows(4).position(|w| w == b"\r\n\r\n") {
+        response.truncate(i + 4);
+    }
+    response
+}
+
+/// The status code of a response created by the application.
+fn application_status(response: &str) -> Option<u16> {
+    response.lines().next()?.split(' ').nth(1)?.parse().ok()
+}
+
+/// Adds the `Connection` header after the status line of a response created by
+/// the application.
+fn set_connection_header(response: String, keep_alive: bool) -> String {
+    let connection = if keep_alive { "keep-alive" } else { "close" };
+    match response.find("\r\n") {
+        Some(i) => format!("{}\r\nConnection: {}{}", &response[..i], connection, &response[i..]),
+        None => response,
+    }
+}
+
+/// Checks if a read failed because the connection was idle for too long.
+fn is_timeout(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut
+}
+
+/// Opens the file at the absolute path. Returns a response streaming the file
+/// with the content type of the file. A precompressed version of the file is
+/// sent instead if the client accepts its encoding. Conditional requests are
+/// evaluated against the file's `ETag` and `Last-Modified` first, then `GET`
+/// requests with a satisfiable `Range` header receive only the requested ranges.
+fn serve_static_content(request: &Request, abs_path: &str, router: &Router) -> Result<Response, ::std::io::Error> {
+    let precompressed = router.precompressed(abs_path);
+    let encodings: Vec<Encoding> = precompressed.iter().map(|&(e, _)| e).collect();
+    let variant = negotiate(request.header("accept-encoding"), &encodings)
+        .and_then(|encoding| precompressed.iter().find(|&&(e, _)| e == encoding));
+    let file = match variant {
+        Some(&(_, ref path)) => File::open(path)?,
+        None => File::open(&abs_path)?,
+    };
+    let metadata = file.metadata()?;
+    let length = metadata.len();
+    let last_modified = metadata.modified().ok();
+    let etag = file_etag(&metadata);
+    let content_type = router.mime_types.for_path(Path::new(abs_path));
+
+    // Sent with both full and 304 responses.
+    let validators = |response: &mut Response| {
+        response.add_response_headers(&format!("ETag: {}", etag));
+        if let Some(last_modified) = last_modified {
+            response.add_response_headers(&format!("Last-Modified: {}", format_http_date(last_modified)));
+        }
+        for header in router.cache_policy.headers_for(&request.path) {
+            response.add_response_headers(&header);
+        }
+        if !precompressed.is_empty() {
+            let vary = merge_vary(response.header("Vary"));
+            response.set_header("Vary", &vary);
+        }
+    };
+
+    let safe = request.kind == RequestKind::Get || request.kind == RequestKind::Head;
+    match evaluate(&request.headers, safe, &etag, last_modified) {
+        Precondition::Proceed => {}
+        Precondition::NotModified => {
+            let mut response = Response::not_modified();
+            validators(&mut response);
+            return Ok(response);
+        }
+        Precondition::Failed => return Ok(Response::precondition_failed()),
+    }
+
+    let ranges = match request.header("range") {
+        Some(range) if request.kind == RequestKind::Get
+            && if_range_matches(&request.headers, Some(&etag), last_modified) => parse_range(range, length),
+        _ => Ranges::Ignore,
+    };
+    let mut response = match ranges {
+        Ranges::Ignore => Response::http_ok_file(file, length, content_type),
+        Ranges::Satisfiable(ref ranges) if ranges.len() == 1 => {
+            Response::partial_content(file, ranges[0], length, content_type)?
+        }
+        Ranges::Satisfiable(ref ranges) => Response::partial_content_multipart(file, ranges, length, content_type),
+        Ranges::Unsatisfiable => Response::range_not_satisfiable(length),
+    };
+
+    if let Some(&(encoding, _)) = variant {
+        if response.status().is_success() {
+            response.add_response_headers(&format!("Content-Encoding: {}", encoding.name()));
+        }
+    }
+    response.add_response_headers("Accept-Ranges: bytes");
+    validators(&mut response);
+    Ok(response)
 }
 
 #[cfg(test)]
@@ -301,6 +907,7 @@ mod tests {
     use self::curl::easy::Easy;
     use std::path::Path;
     use std::fs::{File, create_dir};
+    use std::io::{BufRead, BufReader};
 
     /// Run a server in a seperate thread then make a request to the server.
     /// Assert the response is a success
@@ -334,6 +941,430 @@ mod tests {
 
     }
 
+    /// Sends two pipelined requests on one connection. Both should be answered
+    /// in order and the connection closed after the second asks for it.
+    #[test]
+    fn test_keep_alive_pipelining() {
+        let (static_path, _test_dir) = create_test_dir().unwrap();
+
+        let mut config = create_test_config("9997");
+        config.static_folder = Some(static_path);
+        let port = serve_test_config(config);
+
+        let response = send(&port, b"GET /static/index.html HTTP/1.1\r\nHost: localhost\r\n\r\n\
+                                   GET /missing HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+
+        let first = response.find("HTTP/1.1 200 OK").expect("Missing first response");
+        let second = response.find("HTTP/1.1 404 Not Found").expect("Missing second response");
+        assert!(first < second);
+        assert!(response.contains("Content-Type: text/html; charset=utf-8"));
+        assert!(response.contains("Connection: keep-alive"));
+        assert!(response.contains("Connection: close"));
+    }
+
+    /// Malformed requests are answered with an error status instead of
+    /// killing the worker handling the connection.
+    #[test]
+    fn test_malformed_request() {
+        let port = serve_test_config(create_test_config("9995"));
+
+        assert!(send(&port, b"GET /\r\n\r\n").starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(send(&port, b"GET / HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(send(&port, b"BREW /pot HTTP/1.1\r\nHost: localhost\r\n\r\n").starts_with("HTTP/1.1 501 Not Implemented\r\n"));
+        let response = send(&port, b"TRACE / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+        assert!(response.contains("\r\nAllow: GET, HEAD, POST, PUT, DELETE, OPTIONS\r\n"));
+    }
+
+    /// Requests to a registered route are answered by its handler and a
+    /// panicking handler results in a 500 response.
+    #[test]
+    fn test_handler_routes() {
+        let mut server = Server::from_config(create_test_config("9994"));
+        server.route("/hello", |request: &Request| {
+            Response::http_ok(format!("Hello {}", request.query("name").unwrap_or("world")))
+        });
+        server.get("/users/:id<int>", |request: &Request| {
+            Response::http_ok(format!("User {}", request.params.parse::<u32>("id").unwrap()))
+        });
+        server.route("/panic", |_: &Request| -> Response { panic!("handler failed") });
+        let port = serve_test_server(server);
+
+        let response = send(&port, b"GET /hello?name=rust HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nHello rust"));
+
+        let response = send(&port, b"GET /users/7 HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        assert!(response.ends_with("\r\n\r\nUser 7"));
+        let response = send(&port, b"HEAD /hello?name=rust HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("\r\nContent-Length: 10\r\n"));
+        assert!(response.ends_with("\r\n\r\n"));
+
+        let response = send(&port, b"POST /users/7 HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+        assert!(response.contains("\r\nAllow: GET, HEAD, OPTIONS\r\n"));
+        let response = send(&port, b"GET /users/me HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+        let response = send(&port, b"GET /panic HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+    }
+
+    /// Large static files are streamed (with sendfile on Linux) rather than
+    /// read into memory, the client should receive the whole file.
+    #[test]
+    fn test_large_static_file() {
+        let (static_path, _test_dir) = create_test_dir().unwrap();
+        let contents: Vec<u8> = (0..1024 * 1024 + 7).map(|i| (i % 251) as u8).collect();
+        File::create(Path::new(&static_path).join("large.bin")).unwrap().write_all(&contents).unwrap();
+
+        let mut config = create_test_config("9993");
+        config.static_folder = Some(static_path);
+        let port = serve_test_config(config);
+
+        let response = get_bytes(&port, "/static/large.bin", "");
+
+        let head_end = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        let head = String::from_utf8_lossy(&response[..head_end]).to_string();
+        assert!(head.contains(&format!("\r\nContent-Length: {}\r\n", contents.len())));
+        assert!(head.contains("\r\nContent-Type: application/octet-stream\r\n"));
+        assert!(&response[head_end..] == &contents[..]);
+    }
+
+    #[test]
+    fn test_range_requests() {
+        let (static_path, _test_dir) = create_test_dir().unwrap();
+        File::create(Path::new(&static_path).join("digits.txt")).unwrap().write_all(b"0123456789").unwrap();
+
+        let mut config = create_test_config("9992");
+        config.static_folder = Some(static_path);
+        let port = serve_test_config(config);
+
+        let send = |range: &str| get(&port, "/static/digits.txt", &format!("Range: {}\r\n", range));
+
+        let response = send("bytes=2-4");
+        assert!(response.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+        assert!(response.contains("\r\nContent-Range: bytes 2-4/10\r\n"));
+        assert!(response.contains("\r\nAccept-Ranges: bytes\r\n"));
+        assert!(response.ends_with("\r\n\r\n234"));
+
+        let response = send("bytes=0-1,-2");
+        assert!(response.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+        assert!(response.contains("\r\nContent-Type: multipart/byteranges; boundary="));
+        assert!(response.contains("\r\nContent-Range: bytes 0-1/10\r\n\r\n01\r\n--"));
+        assert!(response.contains("\r\nContent-Range: bytes 8-9/10\r\n\r\n89\r\n--"));
+        let length: usize = response.split("Content-Length: ").nth(1).unwrap()
+            .split("\r\n").next().unwrap().parse().unwrap();
+        assert_eq!(length, response.len() - response.find("\r\n\r\n").unwrap() - 4);
+
+        let response = send("bytes=20-");
+        assert!(response.starts_with("HTTP/1.1 416 Range Not Satisfiable\r\n"));
+        assert!(response.contains("\r\nContent-Range: bytes */10\r\n"));
+    }
+
+    #[test]
+    fn test_conditional_requests() {
+        let (static_path, _test_dir) = create_test_dir().unwrap();
+        File::create(Path::new(&static_path).join("cached.txt")).unwrap().write_all(b"cached").unwrap();
+
+        let mut config = create_test_config("9991");
+        config.static_folder = Some(static_path);
+        let port = serve_test_config(config);
+
+        let send = |header: &str| get(&port, "/static/cached.txt", header);
+        let header = |response: &str, name: &str| {
+            response.split("\r\n")
+                .find(|l| l.starts_with(name))
+                .map(|l| l[name.len() + 2..].to_string())
+                .unwrap()
+        };
+
+        let response = send("");
+        let etag = header(&response, "ETag");
+        let last_modified = header(&response, "Last-Modified");
+
+        let response = send(&format!("If-None-Match: {}\r\n", etag));
+        assert!(response.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+        assert!(response.contains(&format!("\r\nETag: {}\r\n", etag)));
+        assert!(!response.contains("Content-Length"));
+        assert!(response.ends_with("\r\n\r\n"));
+
+        let response = send(&format!("If-Modified-Since: {}\r\n", last_modified));
+        assert!(response.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+
+        let response = send("If-Match: \"stale\"\r\n");
+        assert!(response.starts_with("HTTP/1.1 412 Precondition Failed\r\n"));
+    }
+
+    #[test]
+    fn test_compressed_static_file() {
+        let (static_path, _test_dir) = create_test_dir().unwrap();
+        let script = "console.log('compressed');\n".repeat(200);
+        File::create(Path::new(&static_path).join("app.js")).unwrap().write_all(script.as_bytes()).unwrap();
+
+        let mut config = create_test_config("9990");
+        config.static_folder = Some(static_path);
+        let port = serve_test_config(config);
+
+        let send = |header: &str| get_bytes(&port, "/static/app.js", header);
+
+        let response = send("Accept-Encoding: gzip;q=0.5, br;q=0.1\r\n");
+        let head_end = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        let head = String::from_utf8_lossy(&response[..head_end]).into_owned();
+        assert!(head.contains("\r\nContent-Encoding: gzip\r\n"));
+        assert!(head.contains("\r\nVary: Accept-Encoding\r\n"));
+        assert!(head.contains(&format!("\r\nContent-Length: {}\r\n", response.len() - head_end)));
+        assert!(response.len() - head_end < script.len());
+
+        let etag = head.split("\r\n").find(|l| l.starts_with("ETag: W/")).unwrap()[6..].to_string();
+        let response = send(&format!("Accept-Encoding: gzip\r\nIf-None-Match: {}\r\n", etag));
+        assert!(response.starts_with(b"HTTP/1.1 304 Not Modified\r\n"));
+
+        let response = String::from_utf8(send("")).unwrap();
+        assert!(!response.contains("Content-Encoding"));
+        assert!(response.ends_with(&script));
+    }
+
+    #[test]
+    fn test_precompressed_static_file() {
+        let (static_path, _test_dir) = create_test_dir().unwrap();
+        File::create(Path::new(&static_path).join("app.css")).unwrap().write_all(b"body {}").unwrap();
+        File::create(Path::new(&static_path).join("app.css.br")).unwrap().write_all(b"brotli").unwrap();
+
+        let mut config = create_test_config("9989");
+        config.static_folder = Some(static_path);
+        let port = serve_test_config(config);
+
+        let response = get(&port, "/static/app.css", "Accept-Encoding: gzip, br\r\n");
+        assert!(response.contains("\r\nContent-Type: text/css; charset=utf-8\r\n"));
+        assert!(response.contains("\r\nContent-Encoding: br\r\n"));
+        assert!(response.contains("\r\nVary: Accept-Encoding\r\n"));
+        assert!(response.ends_with("\r\n\r\nbrotli"));
+
+        let response = get(&port, "/static/app.css", "Accept-Encoding: gzip\r\n");
+        assert!(!response.contains("Content-Encoding"));
+        assert!(response.contains("\r\nVary: Accept-Encoding\r\n"));
+        assert!(response.ends_with("\r\n\r\nbody {}"));
+
+        assert!(get(&port, "/static/app.css.br", "").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn test_directory_listing() {
+        let (static_path, _test_dir) = create_test_dir().unwrap();
+        create_dir(Path::new(&static_path).join("docs")).unwrap();
+        File::create(Path::new(&static_path).join("docs").join("guide.txt")).unwrap().write_all(b"guide").unwrap();
+        File::create(Path::new(&static_path).join("docs").join(".hidden")).unwrap();
+
+        let mut config = create_test_config("9988");
+        config.static_folder = Some(static_path);
+        config.autoindex = Some(true);
+        let port = serve_test_config(config);
+
+        let send = |target: &str| get(&port, target, "");
+
+        let response = send("/static/docs/");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("<a href=\"/static/docs/guide.txt\">guide.txt</a>"));
+        assert!(response.contains("<a href=\"/static/\">../</a>"));
+        assert!(!response.contains(".hidden"));
+
+        let response = send("/static/docs?format=json");
+        assert!(response.starts_with("HTTP/1.1 301 Moved Permanently\r\n"));
+        assert!(response.contains("\r\nLocation: /static/docs/?format=json\r\n"));
+
+        let response = send("/static/docs/?format=json");
+        assert!(response.contains("Content-Type: application/json"));
+        assert!(response.contains("\"name\":\"guide.txt\",\"is_dir\":false,\"size\":5"));
+    }
+
+    #[test]
+    fn test_mounts() {
+        let (static_path, test_dir) = create_test_dir().unwrap();
+        File::create(test_dir.path().join("secret.txt")).unwrap().write_all(b"secret").unwrap();
+
+        let mut config = create_test_config("9985");
+        config.mounts = Some(vec![Mount::new("/files", &static_path)]);
+        let port = serve_test_config(config);
+
+        let send = |target: &str| get(&port, target, "");
+
+        // Files created after the server started are served.
+        File::create(Path::new(&static_path).join("notes.txt")).unwrap().write_all(b"notes").unwrap();
+        let response = send("/files/notes.txt");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("notes"));
+
+        assert!(send("/files/../secret.txt").starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(send("/files/..%2Fsecret.txt").starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(send("/files/missing.txt").starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+        #[cfg(unix)]
+        {
+            ::std::os::unix::fs::symlink(test_dir.path().join("secret.txt"),
+                                         Path::new(&static_path).join("link.txt")).unwrap();
+            assert!(send("/files/link.txt").starts_with("HTTP/1.1 403 Forbidden\r\n"));
+        }
+    }
+
+    #[test]
+    fn test_spa_fallback() {
+        let (static_path, _test_dir) = create_test_dir().unwrap();
+        let index = Path::new(&static_path).join("app.html");
+        File::create(&index).unwrap().write_all(b"<div id=\"root\"></div>").unwrap();
+
+        let mut config = create_test_config("9987");
+        config.static_folder = Some(static_path);
+        config.spa_fallback = Some(index.to_str().unwrap().to_string());
+        let port = serve_test_config(config);
+
+        let send = |target: &str, accept: &str| get(&port, target, &format!("Accept: {}\r\n", accept));
+
+        let response = send("/app/settings/profile", "text/html,*/*;q=0.8");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("\r\nContent-Type: text/html; charset=utf-8\r\n"));
+        assert!(response.ends_with("<div id=\"root\"></div>"));
+
+        assert!(send("/static/missing.js", "text/html").starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(send("/api/users", "application/json").starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(send("/app/settings", "text/html;q=0").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn test_live_reload() {
+        let (static_path, _test_dir) = create_test_dir().unwrap();
+        File::create(Path::new(&static_path).join("page.html")).unwrap()
+            .write_all(b"<html><body>hi</body></html>").unwrap();
+        let folder = Path::new(&static_path).file_name().unwrap().to_str().unwrap().to_string();
+
+        let mut config = create_test_config("9986");
+        config.static_folder = Some(static_path.clone());
+        config.dev = Some(true);
+        let port = serve_test_config(config);
+
+        let response = get(&port, &format!("/{}/page.html", folder), "");
+        assert!(response.contains("new EventSource(\"/__livereload\")"));
+        assert!(response.ends_with("</script>\n</body></html>"));
+
+        let response = send(&port, b"POST /__livereload HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+        assert!(response.contains("\r\nAllow: GET\r\n"));
+
+        let mut events = connect(&port);
+        events.write_all(b"GET /__livereload HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut lines = BufReader::new(events).lines().map(|l| l.unwrap());
+        assert_eq!("HTTP/1.1 200 OK", lines.next().unwrap());
+        assert!(lines.any(|l| l == "Content-Type: text/event-stream"));
+        assert!(lines.any(|l| l == "retry: 1000"));
+
+        File::create(Path::new(&static_path).join("new.html")).unwrap();
+        assert!(lines.any(|l| l == "data: reload"));
+    }
+
+    #[test]
+    fn test_application_status() {
+        assert_eq!(Some(404), application_status("HTTP/1.1 404 NOT FOUND\r\nContent-Length: 0\r\n\r\n"));
+        assert_eq!(Some(200), application_status("HTTP/1.1 200 OK\r\n\r\n"));
+        assert_eq!(None, application_status(""));
+    }
+
+    #[test]
+    fn test_application_response_without_body() {
+        let response = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello".to_vec();
+
+        assert_eq!(&b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n"[..], &without_body(response)[..]);
+    }
+
+    /// Runs a server for the config in a separate thread and returns its port.
+    fn serve_test_config(config: Config) -> String {
+        serve_test_server(Server::from_config(config))
+    }
+
+    /// Runs the server in a separate thread and returns its port.
+    fn serve_test_server(server: Server) -> String {
+        let port = server.config.port.clone();
+        thread::spawn(move || { server.serve(); } );
+        port
+    }
+
+    /// Connects to the test server, waiting for it to start listening.
+    fn connect(port: &str) -> TcpStream {
+        for _ in 0..50 {
+            if let Ok(stream) = TcpStream::connect(format!("127.0.0.1:{}", port)) {
+                return stream;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        panic!("Could not connect to the test server");
+    }
+
+    /// Writes a request to the test server and reads the response until the
+    /// server closes the connection.
+    fn send(port: &str, request: &[u8]) -> String {
+        let mut stream = connect(port);
+        stream.write_all(request).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    /// Sends a GET request for `target` with extra header lines, each ending
+    /// in `\r\n`, and reads the response until the server closes the connection.
+    fn get(port: &str, target: &str, headers: &str) -> String {
+        String::from_utf8(get_bytes(port, target, headers)).unwrap()
+    }
+
+    /// Like `get` for responses that aren't text.
+    fn get_bytes(port: &str, target: &str, headers: &str) -> Vec<u8> {
+        let mut stream = connect(port);
+        let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n{}Connection: close\r\n\r\n",
+                              target, headers);
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        response
+    }
+
+    /// Stops a server through its shutdown handle while a persistent connection
+    /// is open. The connection should be closed after its next response, `serve`
+    /// should return and the listener should be closed.
+    #[test]
+    fn test_shutdown() {
+        let (static_path, _test_dir) = create_test_dir().unwrap();
+
+        let test_port = "9996";
+        let config = create_test_config(test_port);
+        let mut server = Server::from_config(config);
+        server.router.register_static_routes(static_path.as_str());
+        let handle = server.shutdown_handle();
+
+        let (tx, rx) = ::std::sync::mpsc::channel();
+        thread::spawn(move || {
+            server.serve();
+            tx.send(()).unwrap();
+        });
+
+        let request = b"GET /static/index.html HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        let mut stream = connect(test_port);
+        stream.write_all(request).unwrap();
+        let mut buf = [0u8; 1024];
+        let n = stream.read(&mut buf).unwrap();
+        assert!(String::from_utf8_lossy(&buf[..n]).contains("Connection: keep-alive"));
+
+        handle.shutdown();
+
+        // The open connection is still answered but is then closed
+        stream.write_all(request).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("Connection: close"));
+
+        rx.recv_timeout(Duration::from_secs(5)).expect("Server did not stop");
+        assert!(TcpStream::connect(format!("127.0.0.1:{}", test_port)).is_err());
+    }
+
     #[test]
     fn test_serve_can_500() {
         let test_port = "5000";
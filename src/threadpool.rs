@@ -1,7 +1,9 @@
 //! The server threadpool
-use std::sync::{Arc, Mutex, mpsc};
+use std::sync::{Arc, Mutex, Condvar, mpsc};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 use std::marker::Sync;
+use std::time::{Duration, Instant};
 
 /// This implementation of `ThreadPool` is based of the one in [the
 /// book](https://doc.rust-lang.org/book/second-edition/ch20-04-storing-threads.html). The
@@ -9,6 +11,11 @@ use std::marker::Sync;
 pub struct ThreadPool {
     workers : Vec<Worker>,
     sender: mpsc::Sender<Message>,
+    running: Arc<(Mutex<usize>, Condvar)>,
+    terminated: AtomicBool,
+    /// Set when workers were left running after a shutdown so dropping the
+    /// pool doesn't wait for them.
+    detached: AtomicBool,
 }
 
 struct Worker {
@@ -49,14 +56,18 @@ impl ThreadPool {
         let (sender, receiver) = mpsc::channel();
 
         let receiver = Arc::new(Mutex::new(receiver));
+        let running = Arc::new((Mutex::new(size), Condvar::new()));
 
         for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
+            workers.push(Worker::new(id, Arc::clone(&receiver), Arc::clone(&running)));
         }
 
         ThreadPool {
             workers,
             sender,
+            running,
+            terminated: AtomicBool::new(false),
+            detached: AtomicBool::new(false),
         }
     }
 
@@ -83,12 +94,48 @@ impl ThreadPool {
             s.send(Message::NewJob(job)).unwrap();
         })
     }
-}
 
-impl Drop for ThreadPool {
-    fn drop(&mut self) {
-        for _ in &mut self.workers {
-            self.sender.send(Message::Terminate).unwrap();
+    /// Tells every worker to terminate once the jobs already sent to the pool
+    /// have been executed, then waits up to `grace_period` for them to finish.
+    /// Returns `false` if some workers were still running when the grace
+    /// period ended; those workers are detached.
+    pub fn shutdown(&self, grace_period: Duration) -> bool {
+        self.terminate();
+
+        let deadline = Instant::now() + grace_period;
+        let &(ref lock, ref condvar) = &*self.running;
+        let mut running = lock.lock().unwrap();
+        while *running > 0 {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            running = condvar.wait_timeout(running, deadline - now).unwrap().0;
+        }
+        let finished = *running == 0;
+        drop(running);
+
+        if !finished {
+            warn!("Worker(s) still running after the grace period. Detaching them.");
+            self.detached.store(true, Ordering::SeqCst);
+        }
+        finished
+    }
+
+    /// Sends a terminate message to each worker. Messages are only sent once.
+    fn terminate(&self) {
+        if self.terminated.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        for _ in &self.workers {
+            // The send can only fail if every worker has already exited
+            let _ = self.sender.send(Message::Terminate);
+        }
+    }
+
+    fn join(&mut self) {
+        if self.detached.load(Ordering::SeqCst) {
+            return;
         }
         for worker in &mut self.workers {
             debug!("Shutting down worker {}", worker.id);
@@ -100,10 +147,20 @@ impl Drop for ThreadPool {
     }
 }
 
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.terminate();
+        self.join();
+    }
+}
+
 impl Worker {
 
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Message>>>) -> Worker {
+    fn new(id: usize,
+           receiver: Arc<Mutex<mpsc::Receiver<Message>>>,
+           running: Arc<(Mutex<usize>, Condvar)>) -> Worker {
         let thread = thread::spawn(move || {
+            let _guard = RunningGuard(running);
             loop{
                 let message = receiver.lock().unwrap().recv().unwrap();
                 
@@ -129,12 +186,27 @@ impl Worker {
     }
 }
 
+/// Decrements the count of running workers when a worker thread exits, even if
+/// a job panicked.
+struct RunningGuard(Arc<(Mutex<usize>, Condvar)>);
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        let &(ref lock, ref condvar) = &*self.0;
+        if let Ok(mut running) = lock.lock() {
+            *running -= 1;
+        }
+        condvar.notify_all();
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use threadpool;
     use std::thread;
     use std::sync::mpsc;
+    use std::time::Duration;
     
     #[test]
     fn assert_thread_pool_size_correct() {
@@ -177,6 +249,31 @@ mod tests {
         assert!(received);
     }
 
+    #[test]
+    fn assert_shutdown_finishes_sent_jobs() {
+        let (tx, rx) = mpsc::channel();
+
+        let pool = threadpool::ThreadPool::new(2);
+        for _ in 0..4 {
+            let tx = tx.clone();
+            pool.execute(move || {
+                thread::sleep(Duration::from_millis(50));
+                tx.send(true).unwrap();
+            });
+        }
+
+        assert!(pool.shutdown(Duration::from_secs(5)));
+        assert_eq!(4, rx.try_iter().count());
+    }
+
+    #[test]
+    fn assert_shutdown_gives_up_after_grace_period() {
+        let pool = threadpool::ThreadPool::new(1);
+        pool.execute(move || { thread::sleep(Duration::from_secs(2)); });
+
+        assert!(!pool.shutdown(Duration::from_millis(50)));
+    }
+
     #[test]
     fn assert_executor_works() {
 
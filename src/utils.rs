@@ -57,7 +57,141 @@ pub mod file {
     }
 }
 
-#[macro_export]
+pub mod url {
+
+    /// Decodes `%XX` escapes in a URL component. Invalid escapes are left as
+    /// they are and invalid utf8 is replaced with U+FFFD.
+    pub fn percent_decode(input: &str) -> String {
+        decode(input, false)
+    }
+
+    /// Decodes a key or value of an `application/x-www-form-urlencoded` query
+    /// string, where `+` is also used for a space.
+    pub fn form_decode(input: &str) -> String {
+        decode(input, true)
+    }
+
+    fn decode(input: &str, plus_as_space: bool) -> String {
+        let bytes = input.as_bytes();
+        let mut decoded = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'%' if i + 2 < bytes.len() => {
+                    match (hex_value(bytes[i + 1]), hex_value(bytes[i + 2])) {
+                        (Some(high), Some(low)) => {
+                            decoded.push(high << 4 | low);
+                            i += 3;
+                            continue;
+                        }
+                        _ => decoded.push(b'%'),
+                    }
+                }
+                b'+' if plus_as_space => decoded.push(b' '),
+                b => decoded.push(b),
+            }
+            i += 1;
+        }
+        String::from_utf8_lossy(&decoded).into_owned()
+    }
+
+    /// Encodes every byte of a path except unreserved characters (RFC 3986
+    /// 2.3) and `/` as `%XX`.
+    pub fn percent_encode_path(input: &str) -> String {
+        let mut encoded = String::with_capacity(input.len());
+        for &b in input.as_bytes() {
+            match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                    encoded.push(b as char)
+                }
+                b => encoded.push_str(&format!("%{:02X}", b)),
+            }
+        }
+        encoded
+    }
+
+    fn hex_value(b: u8) -> Option<u8> {
+        match b {
+            b'0'..=b'9' => Some(b - b'0'),
+            b'a'..=b'f' => Some(b - b'a' + 10),
+            b'A'..=b'F' => Some(b - b'A' + 10),
+            _ => None,
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+
+        use super::*;
+
+        #[test]
+        fn test_percent_decode() {
+            assert_eq!("/my files/\u{e9}t\u{e9}.txt", percent_decode("/my%20files/%C3%A9t%c3%a9.txt"));
+            assert_eq!("a+b", percent_decode("a+b"));
+            assert_eq!("100%", percent_decode("100%"));
+            assert_eq!("%zz%4", percent_decode("%zz%4"));
+        }
+
+        #[test]
+        fn test_percent_encode_path() {
+            assert_eq!("/my%20files/%C3%A9t%C3%A9.txt", percent_encode_path("/my files/\u{e9}t\u{e9}.txt"));
+            assert_eq!("/a%3Fb%23c", percent_encode_path("/a?b#c"));
+        }
+
+        #[test]
+        fn test_form_decode() {
+            assert_eq!("a b&c", form_decode("a+b%26c"));
+        }
+    }
+}
+
+pub mod date {
+    extern crate chrono;
+
+    use self::chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    const IMF_FIXDATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+    const RFC_850: &str = "%A, %d-%b-%y %H:%M:%S GMT";
+    const ASCTIME: &str = "%a %b %e %H:%M:%S %Y";
+
+    /// Formats a time as an HTTP-date, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+    pub fn format_http_date(time: SystemTime) -> String {
+        DateTime::<Utc>::from(time).format(IMF_FIXDATE).to_string()
+    }
+
+    /// Parses an HTTP-date in any of the formats allowed by RFC 7231 7.1.1.1.
+    pub fn parse_http_date(value: &str) -> Option<SystemTime> {
+        [IMF_FIXDATE, RFC_850, ASCTIME].iter()
+            .filter_map(|format| NaiveDateTime::parse_from_str(value.trim(), format).ok())
+            .next()
+            .map(|naive| SystemTime::from(Utc.from_utc_datetime(&naive)))
+    }
+
+    /// Returns the whole seconds since the epoch. HTTP-dates only have a
+    /// resolution of one second so times are compared with this.
+    pub fn unix_seconds(time: SystemTime) -> u64 {
+        time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::time::Duration;
+
+        #[test]
+        fn test_http_dates() {
+            let time = UNIX_EPOCH + Duration::from_secs(784111777);
+
+            assert_eq!("Sun, 06 Nov 1994 08:49:37 GMT", format_http_date(time));
+            assert_eq!(Some(time), parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"));
+            assert_eq!(Some(time), parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"));
+            assert_eq!(Some(time), parse_http_date("Sun Nov  6 08:49:37 1994"));
+            assert_eq!(None, parse_http_date("yesterday"));
+        }
+    }
+}
+
 macro_rules! vprintln {
     ($($arg:tt)*) => {{
         if ::utils::print::is_verbose() {
@@ -69,6 +203,7 @@ macro_rules! vprintln {
 #[allow(dead_code)]
 pub mod print {
     use std::fmt;
+    use std::sync::atomic::Ordering;
 
     /// Function version of the vprintln macro. Does not support formatting 
     /// (use format! within it).
@@ -79,9 +214,7 @@ pub mod print {
     }
 
     pub fn is_verbose() -> bool {
-        unsafe {
-            ::VERBOSE
-        }
+        ::VERBOSE.load(Ordering::Relaxed)
     }
 
     macro_rules! define_colors {
@@ -0,0 +1,423 @@
+//! Keeps the static routes of a running server up to date with the files on
+//! disk. On Linux changes are reported by inotify, elsewhere (or when a poll
+//! interval is configured) the static folders are scanned periodically.
+extern crate walkdir;
+
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use self::walkdir::WalkDir;
+
+use routing::SharedRouter;
+use utils::file::is_hidden;
+
+/// The poll interval used when inotify isn't available.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// Starts a thread updating the router's static routes as files in its
+/// static folders change. Polls the folders every `poll_interval` if it is
+/// given or inotify can't be used. The thread stops with the server.
+pub fn watch(router: SharedRouter, poll_interval: Option<Duration>, stopping: Arc<AtomicBool>) -> io::Result<()> {
+    let folders = router.load().static_folders.clone();
+    if poll_interval.is_none() {
+        match inotify::Watcher::new(&folders) {
+            Ok(watcher) => {
+                info!("Watching {:?} for changes", folders);
+                thread::Builder::new()
+                    .name("static-watcher".to_string())
+                    .spawn(move || watcher.run(&router, &stopping))?;
+                return Ok(());
+            }
+            Err(e) => warn!("Unable to watch for changes ({}), polling instead", e),
+        }
+    }
+
+    let interval = poll_interval.unwrap_or(DEFAULT_POLL_INTERVAL);
+    info!("Polling {:?} for changes every {:?}", folders, interval);
+    // Changes made once this returns are picked up by the first scan.
+    let initial = snapshot(&folders);
+    thread::Builder::new()
+        .name("static-watcher".to_string())
+        .spawn(move || poll(&router, &folders, initial, interval, &stopping))?;
+    Ok(())
+}
+
+/// The type, size and modification time of a file.
+type Snapshot = HashMap<PathBuf, (bool, u64, Option<SystemTime>)>;
+
+/// Scans the folders every interval and refreshes the paths that were added,
+/// removed or changed since the last scan.
+fn poll(router: &SharedRouter, folders: &[String], mut previous: Snapshot, interval: Duration, stopping: &AtomicBool) {
+    while !stopping.load(Ordering::SeqCst) {
+        thread::sleep(interval);
+        let current = snapshot(folders);
+        apply_changes(router, &changed_paths(&previous, &current));
+        previous = current;
+    }
+}
+
+/// Refreshes the routes for the changed paths, then tells the browsers to
+/// reload in development mode.
+fn apply_changes(router: &SharedRouter, changed: &[PathBuf]) {
+    if changed.is_empty() {
+        return;
+    }
+    debug!("Static files changed: {:?}", changed);
+    router.update(|router| {
+        for path in changed {
+            router.refresh_static_path(path);
+        }
+    });
+    if let Some(ref live_reload) = router.load().live_reload {
+        live_reload.reload();
+    }
+}
+
+fn snapshot(folders: &[String]) -> Snapshot {
+    let mut files = HashMap::new();
+    for folder in folders {
+        for entry in WalkDir::new(folder).into_iter().filter_entry(|e| !is_hidden(e)) {
+            if let Ok(entry) = entry {
+                if let Ok(metadata) = entry.metadata() {
+                    let state = (metadata.is_dir(), metadata.len(), metadata.modified().ok());
+                    files.insert(entry.path().to_path_buf(), state);
+                }
+            }
+        }
+    }
+    files
+}
+
+/// Returns the paths that differ between two snapshots. Paths within a
+/// changed directory are left out since refreshing the directory covers them.
+fn changed_paths(previous: &Snapshot, current: &Snapshot) -> Vec<PathBuf> {
+    let mut changed: Vec<PathBuf> = current.iter()
+        .filter(|&(path, state)| previous.get(path) != Some(state))
+        .map(|(path, _)| path.clone())
+        .chain(previous.keys().filter(|path| !current.contains_key(*path)).cloned())
+        .collect();
+    // Directories change when their entries do so only new or removed
+    // directories are refreshed as a whole.
+    changed.retain(|path| {
+        let is_dir = |snapshot: &Snapshot| snapshot.get(path).map_or(false, |&(dir, _, _)| dir);
+        !(is_dir(previous) && is_dir(current))
+    });
+    changed.sort();
+    let mut result: Vec<PathBuf> = Vec::new();
+    for path in changed {
+        if !result.iter().any(|parent| path.starts_with(parent)) {
+            result.push(path);
+        }
+    }
+    result
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    extern crate libc;
+
+    use std::collections::HashMap;
+    use std::ffi::{CString, OsStr};
+    use std::io;
+    use std::mem;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::ptr;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::walkdir::WalkDir;
+
+    use routing::SharedRouter;
+    use utils::file::is_hidden;
+
+    /// The events that can change the routes: files and directories being
+    /// created, removed, renamed or written.
+    const MASK: u32 = libc::IN_CREATE | libc::IN_DELETE | libc::IN_MOVED_FROM | libc::IN_MOVED_TO
+        | libc::IN_CLOSE_WRITE | libc::IN_DELETE_SELF;
+
+    /// How long to wait for events in milliseconds before checking whether
+    /// the server is stopping.
+    const WAIT_TIMEOUT: libc::c_int = 200;
+
+    pub struct Watcher {
+        fd: libc::c_int,
+        /// The directory watched by each watch descriptor.
+        watches: HashMap<libc::c_int, PathBuf>,
+    }
+
+    impl Watcher {
+        /// Creates an inotify instance watching every directory in the folders.
+        pub fn new(folders: &[String]) -> io::Result<Watcher> {
+            let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut watcher = Watcher {
+                fd,
+                watches: HashMap::new(),
+            };
+            for folder in folders {
+                watcher.add_watches(Path::new(folder))?;
+            }
+            Ok(watcher)
+        }
+
+        /// Watches the directory and every directory within it.
+        fn add_watches(&mut self, dir: &Path) -> io::Result<()> {
+            for entry in WalkDir::new(dir).into_iter().filter_entry(|e| !is_hidden(e)) {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(_) => continue,
+                };
+                if !entry.file_type().is_dir() {
+                    continue;
+                }
+                let path = CString::new(entry.path().as_os_str().as_bytes())?;
+                let wd = unsafe { libc::inotify_add_watch(self.fd, path.as_ptr(), MASK) };
+                if wd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                self.watches.insert(wd, entry.path().to_path_buf());
+            }
+            Ok(())
+        }
+
+        /// Stops watching the directory and every directory within it.
+        fn remove_watches(&mut self, dir: &Path) {
+            let removed: Vec<libc::c_int> = self.watches.iter()
+                .filter(|&(_, path)| path.starts_with(dir))
+                .map(|(&wd, _)| wd)
+                .collect();
+            for wd in removed {
+                unsafe {
+                    libc::inotify_rm_watch(self.fd, wd);
+                }
+                self.watches.remove(&wd);
+            }
+        }
+
+        /// Waits up to `WAIT_TIMEOUT` for events. Returns whether there are
+        /// events to read.
+        fn wait(&self) -> io::Result<bool> {
+            let mut pollfd = libc::pollfd { fd: self.fd, events: libc::POLLIN, revents: 0 };
+            match unsafe { libc::poll(&mut pollfd, 1, WAIT_TIMEOUT) } {
+                ready if ready < 0 => Err(io::Error::last_os_error()),
+                ready => Ok(ready > 0),
+            }
+        }
+
+        /// Reads events until the server stops, refreshing the routes for the
+        /// paths in each batch of events.
+        pub fn run(mut self, router: &SharedRouter, stopping: &AtomicBool) {
+            let mut buf = vec![0u8; 64 * 1024];
+            while !stopping.load(Ordering::SeqCst) {
+                match self.wait() {
+                    Ok(true) => {}
+                    Ok(false) => continue,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        error!("Error waiting for file system events: {:?}", e);
+                        break;
+                    }
+                }
+                let n = unsafe { libc::read(self.fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+                if n < 0 {
+                    let e = io::Error::last_os_error();
+                    if e.kind() == io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    error!("Error reading file system events: {:?}", e);
+                    break;
+                }
+
+                let mut changed: Vec<PathBuf> = Vec::new();
+                for (wd, mask, name) in parse_events(&buf[..n as usize]) {
+                    if mask & libc::IN_IGNORED != 0 {
+                        self.watches.remove(&wd);
+                        continue;
+                    }
+                    let path = match (self.watches.get(&wd), name) {
+                        (Some(dir), Some(name)) => dir.join(name),
+                        (Some(dir), None) => dir.clone(),
+                        (None, _) => continue,
+                    };
+                    // The watches of a moved directory keep reporting its old
+                    // path, so they are removed and added again at the new one.
+                    if mask & libc::IN_ISDIR != 0 && mask & libc::IN_MOVED_FROM != 0 {
+                        self.remove_watches(&path);
+                    }
+                    if mask & libc::IN_ISDIR != 0 && mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0 {
+                        if let Err(e) = self.add_watches(&path) {
+                            error!("Unable to watch {:?}: {:?}", path, e);
+                        }
+                    }
+                    if !changed.contains(&path) {
+                        changed.push(path);
+                    }
+                }
+                super::apply_changes(router, &changed);
+            }
+        }
+    }
+
+    impl Drop for Watcher {
+        fn drop(&mut self) {
+            unsafe {
+                libc::close(self.fd);
+            }
+        }
+    }
+
+    /// Parses a buffer of `inotify_event`s into the watch descriptor, mask and
+    /// name of each event.
+    fn parse_events(buf: &[u8]) -> Vec<(libc::c_int, u32, Option<&OsStr>)> {
+        let header = mem::size_of::<libc::inotify_event>();
+        let mut events = Vec::new();
+        let mut offset = 0;
+        while offset + header <= buf.len() {
+            let event: libc::inotify_event = unsafe {
+                ptr::read_unaligned(buf[offset..].as_ptr() as *const libc::inotify_event)
+            };
+            let name_start = offset + header;
+            let name_end = (name_start + event.len as usize).min(buf.len());
+            let name = &buf[name_start..name_end];
+            let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+            let name = if name.is_empty() { None } else { Some(OsStr::from_bytes(name)) };
+            events.push((event.wd, event.mask, name));
+            offset = name_end;
+        }
+        events
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod inotify {
+    use std::io;
+    use std::sync::atomic::AtomicBool;
+
+    use routing::SharedRouter;
+
+    pub struct Watcher;
+
+    impl Watcher {
+        pub fn new(_folders: &[String]) -> io::Result<Watcher> {
+            Err(io::Error::new(io::ErrorKind::Other, "inotify is only available on Linux"))
+        }
+
+        pub fn run(self, _router: &SharedRouter, _stopping: &AtomicBool) {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+    use std::fs::{create_dir, remove_file, rename, File};
+    use std::sync::mpsc;
+    use std::time::Instant;
+    use self::tempdir::TempDir;
+
+    use routing::Router;
+
+    /// Waits for the condition to hold for up to two seconds.
+    fn eventually<F: Fn() -> bool>(condition: F) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(2) {
+            if condition() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        false
+    }
+
+    fn check_watcher(poll_interval: Option<Duration>) {
+        let test_dir = TempDir::new("rhs-tests").unwrap();
+        let dir_name = test_dir.path().file_name().unwrap().to_str().unwrap().to_string();
+        File::create(test_dir.path().join("old.txt")).unwrap();
+        let router = SharedRouter::new(Router::from(test_dir.path().to_str().unwrap()));
+        let stopping = Arc::new(AtomicBool::new(false));
+        watch(router.clone(), poll_interval, Arc::clone(&stopping)).unwrap();
+        let url = |name: &str| format!("/{}/{}", dir_name, name);
+
+        create_dir(test_dir.path().join("sub")).unwrap();
+        File::create(test_dir.path().join("sub").join("new.txt")).unwrap();
+        remove_file(test_dir.path().join("old.txt")).unwrap();
+
+        assert!(eventually(|| router.load().is_static_content(&url("sub/new.txt"))));
+        assert!(eventually(|| !router.load().is_static_content(&url("old.txt"))));
+        stopping.store(true, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn test_watcher() {
+        check_watcher(None);
+    }
+
+    #[test]
+    fn test_polling_watcher() {
+        check_watcher(Some(Duration::from_millis(20)));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_watcher_stops() {
+        let test_dir = TempDir::new("rhs-tests").unwrap();
+        let router = SharedRouter::new(Router::from(test_dir.path().to_str().unwrap()));
+        let watcher = inotify::Watcher::new(&router.load().static_folders).unwrap();
+        let stopping = Arc::new(AtomicBool::new(false));
+        let (done, stopped) = mpsc::channel();
+        let thread_stopping = Arc::clone(&stopping);
+        thread::spawn(move || {
+            watcher.run(&router, &thread_stopping);
+            done.send(()).unwrap();
+        });
+
+        stopping.store(true, Ordering::SeqCst);
+        assert!(stopped.recv_timeout(Duration::from_secs(2)).is_ok());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_watcher_follows_moved_directories() {
+        let test_dir = TempDir::new("rhs-tests").unwrap();
+        let dir_name = test_dir.path().file_name().unwrap().to_str().unwrap().to_string();
+        create_dir(test_dir.path().join("old")).unwrap();
+        create_dir(test_dir.path().join("old").join("sub")).unwrap();
+        let router = SharedRouter::new(Router::from(test_dir.path().to_str().unwrap()));
+        let stopping = Arc::new(AtomicBool::new(false));
+        watch(router.clone(), None, Arc::clone(&stopping)).unwrap();
+
+        rename(test_dir.path().join("old"), test_dir.path().join("new")).unwrap();
+        File::create(test_dir.path().join("new").join("sub").join("a.txt")).unwrap();
+
+        let url = format!("/{}/new/sub/a.txt", dir_name);
+        assert!(eventually(|| router.load().is_static_content(&url)));
+        assert!(!router.load().is_static_content(&format!("/{}/old/sub/a.txt", dir_name)));
+        stopping.store(true, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn test_changed_paths() {
+        let state = |dir, len| (dir, len, None);
+        let mut previous = HashMap::new();
+        previous.insert(PathBuf::from("s"), state(true, 0));
+        previous.insert(PathBuf::from("s/a.txt"), state(false, 1));
+        previous.insert(PathBuf::from("s/old"), state(true, 0));
+        previous.insert(PathBuf::from("s/old/b.txt"), state(false, 1));
+        let mut current = HashMap::new();
+        current.insert(PathBuf::from("s"), state(true, 0));
+        current.insert(PathBuf::from("s/a.txt"), state(false, 2));
+        current.insert(PathBuf::from("s/new"), state(true, 0));
+        current.insert(PathBuf::from("s/new/c.txt"), state(false, 1));
+
+        let changed = changed_paths(&previous, &current);
+        assert_eq!(vec![PathBuf::from("s/a.txt"), PathBuf::from("s/new"), PathBuf::from("s/old")], changed);
+    }
+}
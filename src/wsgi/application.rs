@@ -8,7 +8,7 @@ use std::error::Error;
 use std::fmt;
 use std::convert::From;
 
-use self::cpython::{Python, PythonObject, PyString, PyList, PyDict, PythonObjectWithCheckedDowncast, PyResult, PyErr};
+use self::cpython::{Python, PythonObject, PyString, PyBytes, PyList, PyDict, PythonObjectWithCheckedDowncast, PyResult, PyErr};
 use utils::file::locate_file;
 use request::Request;
 use server::{ServerApplication, InternalServerError};
@@ -110,7 +110,7 @@ impl Application {
         let env = PyDict::new(py);
         env.set_item(py, "wsgi.version", "1.0")?;
         env.set_item(py, "wsgi.url_scheme", "http")?;
-        env.set_item(py, "wsgi.input", request.data)?;
+        env.set_item(py, "wsgi.input", PyBytes::new(py, &request.body))?;
         env.set_item(py, "wsgi.errors", "2>")?;
         env.set_item(py, "wsgi.multithread", true)?;
         env.set_item(py, "wsgi.multiprocess", true)?;
@@ -118,8 +118,24 @@ impl Application {
         
         env.set_item(py, "REQUEST_METHOD", format!("{}", request.kind))?;
         env.set_item(py, "PATH_INFO", request.path)?;
+        env.set_item(py, "QUERY_STRING", request.query_string)?;
         env.set_item(py, "SERVER_NAME", format!("{}", request.host))?; 
         env.set_item(py, "SERVER_PORT", format!("{}", self.port))?;
+        env.set_item(py, "SERVER_PROTOCOL", request.version)?;
+
+        for &(ref name, _) in request.headers.iter() {
+            let key = match &*name.to_uppercase().replace('-', "_") {
+                "CONTENT_TYPE" => "CONTENT_TYPE".to_string(),
+                "CONTENT_LENGTH" => "CONTENT_LENGTH".to_string(),
+                other => format!("HTTP_{}", other),
+            };
+            let separator = if key == "HTTP_COOKIE" { "; " } else { ", " };
+            env.set_item(py, key, request.headers.get_all(name).join(separator))?;
+        }
+        if !request.headers.contains("content-length") && !request.body.is_empty() {
+            // Chunked bodies are decoded before reaching the application
+            env.set_item(py, "CONTENT_LENGTH", request.body.len().to_string())?;
+        }
         Ok(env)
     }
 }
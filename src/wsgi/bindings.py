@@ -1,3 +1,4 @@
+import io
 
 class Application:
 
@@ -14,17 +15,23 @@ class Application:
     def call_callable(cls, env, application):
 
         app_state = cls()
+        env['wsgi.input'] = io.BytesIO(env['wsgi.input'])
 
-        body = application(env, app_state.start_response)
+        iterable = application(env, app_state.start_response)
+        body = b''.join(iterable)
+        if hasattr(iterable, 'close'):
+            iterable.close()
 
         status, headers = app_state.headers
+        # The server needs the length to keep the connection open
+        if not any(name.lower() == 'content-length' for name, _ in headers):
+            headers = list(headers) + [('Content-Length', str(len(body)))]
         result = 'HTTP/1.1 {}\r\n'.format(status)
         for h in headers:
             result += '{0}: {1}\r\n'.format(*h)
         result += '\r\n'
 
-        for data in body:
-            result += data.decode()
+        result += body.decode()
 
         return result
         ## Implement function that calls the wsgi app and returns data